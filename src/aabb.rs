@@ -0,0 +1,73 @@
+use crate::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Axis-aligned bounding box, used to quickly discard rays which cannot
+/// possibly hit whatever is inside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Self {
+            min: a.min(&b),
+            max: a.max(&b),
+        }
+    }
+
+    /// a box containing nothing at all. It is the neutral element
+    /// for `surrounding`.
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::from([f64::INFINITY, f64::INFINITY, f64::INFINITY]),
+            max: Vec3::from([f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY]),
+        }
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// slab test: intersect the ray with the three pairs of planes and
+    /// check that the resulting intervals overlap.
+    pub fn hit(&self, ray: &Ray, mut tmin: f64, mut tmax: f64) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.dir[axis];
+            let mut t0 = (self.min[axis] - ray.orig[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.orig[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written this way so that NaN (0 * inf) doesn't shrink the interval
+            if t0 > tmin {
+                tmin = t0;
+            }
+            if t1 < tmax {
+                tmax = t1;
+            }
+            if tmax < tmin {
+                return false;
+            }
+        }
+        true
+    }
+}
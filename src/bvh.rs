@@ -0,0 +1,373 @@
+use crate::{
+    aabb::Aabb,
+    light::Light,
+    ray::{HitRecord, Hittable, Ray},
+    vec3::Point3,
+};
+
+/// how many buckets are used along an axis to evaluate the surface area
+/// heuristic. More buckets gives a (slightly) better tree but a slower build.
+const SAH_BUCKETS: usize = 12;
+
+/// relative cost of traversing an interior node compared to intersecting
+/// a primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+
+/// above this number of primitives, a leaf is always split if possible
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy built with the surface area heuristic (SAH).
+/// The tree is flattened into a vector, and the objects are reordered so that
+/// each leaf references a contiguous range of them.
+#[derive(Debug)]
+pub struct Bvh<T> {
+    objects: Vec<T>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf {
+        bbox: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bbox: Aabb,
+        /// the left child is always the node right after this one
+        right: usize,
+        /// axis used to split the children, used to visit the closest one first
+        axis: usize,
+    },
+}
+
+impl Node {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bbox, .. } => bbox,
+            Node::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// what we need to know about a primitive during the build
+struct BuildInfo {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+impl<T> Bvh<T>
+where
+    T: Hittable,
+{
+    pub fn new(objects: Vec<T>) -> Self {
+        Self::with_bounds(objects, T::bounding_box)
+    }
+}
+
+impl<T> Bvh<T> {
+    /// tree of objects which aren't necessarily hittable, like lights
+    pub fn with_bounds(objects: Vec<T>, bounding_box: impl Fn(&T) -> Aabb) -> Self {
+        let mut infos = objects
+            .iter()
+            .enumerate()
+            .map(|(index, obj)| {
+                let bbox = bounding_box(obj);
+                BuildInfo {
+                    index,
+                    bbox,
+                    centroid: bbox.centroid(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        if !infos.is_empty() {
+            build(&mut nodes, &mut infos, 0);
+        }
+
+        // reorder the objects to match the order of the leaves
+        let mut slots = objects.into_iter().map(Some).collect::<Vec<_>>();
+        let objects = infos
+            .iter()
+            .map(|info| slots[info.index].take().expect("object used twice in bvh"))
+            .collect();
+
+        Self { objects, nodes }
+    }
+
+    /// call `f` on all the objects whose bounding box is hit by the ray,
+    /// (and on a few others, sharing a leaf with them)
+    pub fn for_each_candidate(&self, ray: &Ray, tmin: f64, tmax: f64, mut f: impl FnMut(&T)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.bbox().hit(ray, tmin, tmax) {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, count, .. } => self.objects[*start..*start + *count]
+                    .iter()
+                    .for_each(&mut f),
+                Node::Interior { right, .. } => {
+                    stack.push(*right);
+                    stack.push(idx + 1);
+                }
+            }
+        }
+    }
+}
+
+/// recursively build the tree for the given primitives, pushing the nodes
+/// in depth first order. `offset` is the index of the first element of `infos`
+/// in the final object list.
+fn build(nodes: &mut Vec<Node>, infos: &mut [BuildInfo], offset: usize) {
+    let bbox = infos
+        .iter()
+        .fold(Aabb::empty(), |acc, info| acc.surrounding(&info.bbox));
+
+    let leaf = Node::Leaf {
+        bbox,
+        start: offset,
+        count: infos.len(),
+    };
+
+    if infos.len() == 1 {
+        nodes.push(leaf);
+        return;
+    }
+
+    let centroid_bounds = infos.iter().fold(Aabb::empty(), |acc, info| {
+        acc.surrounding(&Aabb::new(info.centroid, info.centroid))
+    });
+
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        let cmin = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - cmin;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let bucket_of = |info: &BuildInfo| {
+            let b = ((info.centroid[axis] - cmin) / extent * SAH_BUCKETS as f64) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut boxes = [Aabb::empty(); SAH_BUCKETS];
+        for info in infos.iter() {
+            let b = bucket_of(info);
+            counts[b] += 1;
+            boxes[b] = boxes[b].surrounding(&info.bbox);
+        }
+
+        // cost of splitting after each bucket, sweeping from both ends
+        let mut left_area = [0.0; SAH_BUCKETS];
+        let mut left_count = [0; SAH_BUCKETS];
+        let mut acc_box = Aabb::empty();
+        let mut acc_count = 0;
+        for b in 0..SAH_BUCKETS {
+            acc_box = acc_box.surrounding(&boxes[b]);
+            acc_count += counts[b];
+            left_area[b] = acc_box.surface_area();
+            left_count[b] = acc_count;
+        }
+
+        let mut acc_box = Aabb::empty();
+        let mut acc_count = 0;
+        for b in (1..SAH_BUCKETS).rev() {
+            acc_box = acc_box.surrounding(&boxes[b]);
+            acc_count += counts[b];
+            if acc_count == 0 || left_count[b - 1] == 0 {
+                continue;
+            }
+            let cost = left_count[b - 1] as f64 * left_area[b - 1]
+                + acc_count as f64 * acc_box.surface_area();
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    let (cost, axis, split) = match best {
+        Some(b) => b,
+        // all centroids are at the same place, cannot split
+        None => {
+            nodes.push(leaf);
+            return;
+        }
+    };
+
+    let area = bbox.surface_area();
+    let split_cost = if area > 0.0 {
+        TRAVERSAL_COST + cost / area
+    } else {
+        TRAVERSAL_COST
+    };
+    if infos.len() <= MAX_LEAF_SIZE && split_cost >= infos.len() as f64 {
+        nodes.push(leaf);
+        return;
+    }
+
+    let cmin = centroid_bounds.min[axis];
+    let extent = centroid_bounds.max[axis] - cmin;
+    let mid = partition(infos, |info| {
+        let b = ((info.centroid[axis] - cmin) / extent * SAH_BUCKETS as f64) as usize;
+        b.min(SAH_BUCKETS - 1) < split
+    });
+
+    let idx = nodes.len();
+    nodes.push(Node::Interior {
+        bbox,
+        right: 0,
+        axis,
+    });
+    let (left, right) = infos.split_at_mut(mid);
+    build(nodes, left, offset);
+    let right_idx = nodes.len();
+    build(nodes, right, offset + mid);
+    if let Node::Interior { right, .. } = &mut nodes[idx] {
+        *right = right_idx;
+    }
+}
+
+/// move all elements satisfying the predicate at the beginning of the slice
+/// and return the number of such elements.
+fn partition<F>(infos: &mut [BuildInfo], pred: F) -> usize
+where
+    F: Fn(&BuildInfo) -> bool,
+{
+    let mut mid = 0;
+    for i in 0..infos.len() {
+        if pred(&infos[i]) {
+            infos.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl<T> Hittable for Bvh<T>
+where
+    T: Hittable,
+{
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest_so_far = tmax;
+        let mut hit = None;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.bbox().hit(ray, tmin, closest_so_far) {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, count, .. } => {
+                    for obj in &self.objects[*start..*start + *count] {
+                        if let Some(obj_hit) = obj.hit(ray, tmin, closest_so_far) {
+                            closest_so_far = obj_hit.t;
+                            hit = Some(obj_hit);
+                        }
+                    }
+                }
+                Node::Interior { right, axis, .. } => {
+                    // push the farthest child first so that the closest one
+                    // is visited first, and can shrink `closest_so_far`
+                    if ray.dir[*axis] < 0.0 {
+                        stack.push(idx + 1);
+                        stack.push(*right);
+                    } else {
+                        stack.push(*right);
+                        stack.push(idx + 1);
+                    }
+                }
+            }
+        }
+
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |node| *node.bbox())
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.objects.iter().flat_map(Hittable::lights).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        material::{Material, Sphere},
+        texture::Texture,
+        vec3::Vec3,
+    };
+
+    fn random_vec(rng: &mut StdRng, min: f64, max: f64) -> Vec3 {
+        Vec3::from([
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+        ])
+    }
+
+    fn random_spheres(rng: &mut StdRng, n: usize) -> Vec<Sphere> {
+        (0..n)
+            .map(|_| Sphere {
+                center: random_vec(rng, -10.0, 10.0),
+                radius: rng.gen_range(0.05..1.0),
+                material: Material::Lambertian {
+                    albedo: Texture::Solid(random_vec(rng, 0.0, 1.0)),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bvh_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let linear = random_spheres(&mut rng, 500);
+        let bvh = Bvh::new(random_spheres(&mut StdRng::seed_from_u64(0x5eed), 500));
+
+        let mut hits = 0;
+        for _ in 0..10_000 {
+            let ray = Ray {
+                orig: random_vec(&mut rng, -15.0, 15.0),
+                dir: random_vec(&mut rng, -1.0, 1.0),
+                time: 0.0,
+            };
+            let expected = linear.hit(&ray, 0.0001, f64::INFINITY);
+            let actual = bvh.hit(&ray, 0.0001, f64::INFINITY);
+            assert_eq!(expected, actual, "mismatch for {ray:?}");
+            hits += expected.is_some() as usize;
+        }
+        // make sure the test isn't vacuous
+        assert!(hits > 1000, "only {hits} rays hit something");
+    }
+
+    #[test]
+    fn bvh_bounding_box_contains_everything() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let spheres = random_spheres(&mut rng, 100);
+        let expected = spheres.bounding_box();
+        let bvh = Bvh::new(spheres);
+        assert_eq!(bvh.bounding_box(), expected);
+    }
+}
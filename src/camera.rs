@@ -1,30 +1,34 @@
 use std::f64::consts::PI;
 
 use crate::{
-    ray::Ray,
+    ray::{Hittable, Ray},
+    sampler::{self, Independent, Sampler},
     vec3::{Point3, Vec3},
 };
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Camera {
-    pub(crate) image_width: usize,
-    pub(crate) image_height: usize,
-
-    pub(crate) viewport_width: f64,
-    pub(crate) viewport_height: f64,
-    pub(crate) focal_length: f64,
-    pub(crate) origin: Point3,
-    pub(crate) horizontal: Vec3,
-    pub(crate) vertical: Vec3,
-    pub(crate) lower_left_corner: Vec3,
+pub struct Camera {
+    pub image_width: usize,
+    pub image_height: usize,
+
+    pub viewport_width: f64,
+    pub viewport_height: f64,
+    pub focal_length: f64,
+    pub origin: Point3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    pub lower_left_corner: Vec3,
 
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
-    pub(crate) fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
         look_from: Point3,
         look_at: Point3,
         vup: Vec3, // where does is up for the camera (rotation around the direction of look_at)
@@ -34,6 +38,7 @@ impl Camera {
         focal_length: f64,
         aperture: f64,
         focus_dist: f64,
+        (shutter_open, shutter_close): (f64, f64),
     ) -> Self {
         let theta = vfof * PI / 180.0;
         let h = (theta / 2.0).tan();
@@ -51,7 +56,7 @@ impl Camera {
         let lens_radius = aperture / 2.0;
         Camera {
             image_width,
-            image_height: (image_width as f64 / aspect_ratio).ceil() as _,
+            image_height: (image_width as f64 / aspect_ratio).round() as _,
             viewport_width,
             viewport_height,
             focal_length,
@@ -62,17 +67,302 @@ impl Camera {
             u,
             v,
             lens_radius,
+            shutter_open,
+            shutter_close,
         }
     }
 
-    pub(crate) fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    /// ray through the point (`s`, `t`) of the image, from a point of the
+    /// lens and at a time picked by `sampler`
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * sampler::concentric_disk(sampler.next_2d());
         let offset = self.u * rd.x + self.v * rd.y;
         Ray {
             orig: self.origin + offset,
             dir: self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
                 - offset,
+            time: self.shutter_open + sampler.next_1d() * (self.shutter_close - self.shutter_open),
+        }
+    }
+}
+
+/// closest look_from can get to look_at by zooming in
+const MIN_ZOOM_DISTANCE: f64 = 1e-3;
+
+/// Everything needed to build a camera, except the size of the image which
+/// is only known once we know where the image is going to be displayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    /// vertical field-of-view in degrees
+    pub vfov: f64,
+    pub focal_length: f64,
+    pub aperture: f64,
+    /// defaults to the distance between look_from and look_at
+    pub focus_dist: Option<f64>,
+    /// the rays are cast at random times between these two, blurring the
+    /// moving objects
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            look_from: Vec3::from([13, 2, 3]),
+            look_at: Vec3::from([0, 0, 0]),
+            vup: Vec3::from([0, 1, 0]),
+            vfov: 20.0,
+            focal_length: 1.0,
+            aperture: 0.1,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn to_camera(&self, width: usize, height: usize) -> Camera {
+        let aspect_ratio = if height == 0 {
+            0.0
+        } else {
+            width as f64 / height as f64
+        };
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.look_from - self.look_at).length());
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            width,
+            self.focal_length,
+            self.aperture,
+            focus_dist,
+            (self.shutter_open, self.shutter_close),
+        )
+    }
+
+    /// orthonormal basis of the camera: right, up, and backward
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let w = (self.look_from - self.look_at).unit();
+        let u = self.vup.cross(&w).unit();
+        let v = w.cross(&u);
+        (u, v, w)
+    }
+
+    /// turn around look_at, horizontally (around vup) then vertically.
+    /// Angles are in radians.
+    pub fn orbit(&mut self, yaw: f64, pitch: f64) {
+        self.look_from = self.look_at + self.turn(self.look_from - self.look_at, yaw, pitch);
+    }
+
+    /// move look_from and look_at together, in the plane of the image
+    pub fn pan(&mut self, right: f64, up: f64) {
+        let (u, v, _) = self.basis();
+        let delta = right * u + up * v;
+        self.look_from += delta;
+        self.look_at += delta;
+    }
+
+    /// scale the distance between look_from and look_at, without going
+    /// through look_at
+    pub fn zoom(&mut self, factor: f64) {
+        let offset = self.look_from - self.look_at;
+        let factor = factor.max(MIN_ZOOM_DISTANCE / offset.length());
+        self.look_from = self.look_at + factor * offset;
+    }
+
+    /// move look_from and look_at together, relative to where the camera looks
+    pub fn fly(&mut self, forward: f64, right: f64, up: f64) {
+        let (u, v, w) = self.basis();
+        let delta = right * u + up * v - forward * w;
+        self.look_from += delta;
+        self.look_at += delta;
+    }
+
+    /// turn the camera on itself, moving look_at around look_from
+    pub fn look_around(&mut self, yaw: f64, pitch: f64) {
+        self.look_at = self.look_from + self.turn(self.look_at - self.look_from, yaw, pitch);
+    }
+
+    fn turn(&self, offset: Vec3, yaw: f64, pitch: f64) -> Vec3 {
+        let up = self.vup.unit();
+        let (u, _, _) = self.basis();
+        let offset = rotate(&offset, &up, yaw);
+        let pitched = rotate(&offset, &rotate(&u, &up, yaw), pitch);
+        // stop before going over the poles, where the camera would flip
+        if pitched.unit().dot(&up).abs() < 0.99 {
+            pitched
+        } else {
+            offset
         }
     }
+
+    /// focus distance putting in focus what is seen at `pixel` (from the top
+    /// left corner) of an image of the given size, if there is anything there
+    pub fn focus_distance(
+        &self,
+        world: &dyn Hittable,
+        (width, height): (usize, usize),
+        (x, y): (f64, f64),
+    ) -> Option<f64> {
+        let s = x / width as f64;
+        let t = (height as f64 - y) / height as f64;
+
+        // without aperture, the ray goes exactly through the pixel
+        let pinhole = CameraSettings {
+            aperture: 0.0,
+            ..self.clone()
+        }
+        .to_camera(width, height);
+        // nothing is random without aperture, except for the time
+        let ray = pinhole.get_ray(s, t, &mut Independent::new(0));
+        let hit = world.hit(&ray, 0.0001, f64::INFINITY)?;
+
+        // the focus plane is perpendicular to the view direction, so what
+        // matters is the distance along that direction, not along the ray
+        let forward = (self.look_at - self.look_from).unit();
+        Some((hit.p - ray.orig).dot(&forward))
+    }
+
+    /// size of a pixel at the distance of look_at, for an image with the
+    /// given height. Used to move the scene by as much as the mouse moves.
+    pub fn pixel_size(&self, image_height: usize) -> f64 {
+        let h = (self.vfov * PI / 180.0 / 2.0).tan();
+        2.0 * h * (self.look_from - self.look_at).length() / image_height.max(1) as f64
+    }
+}
+
+/// rotate `v` around the unit vector `axis` (Rodrigues' formula)
+fn rotate(v: &Vec3, axis: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    cos * v + sin * axis.cross(v) + (1.0 - cos) * axis.dot(v) * axis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Material, Sphere},
+        texture::Texture,
+    };
+
+    fn distance(camera: &CameraSettings) -> f64 {
+        (camera.look_from - camera.look_at).length()
+    }
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).length() < 1e-9
+    }
+
+    #[test]
+    fn orbit_keeps_the_distance() {
+        let mut camera = CameraSettings::default();
+        let expected = distance(&camera);
+        for (yaw, pitch) in [(0.3, 0.0), (0.0, 0.2), (-1.2, -0.4), (2.5, 0.7)] {
+            camera.orbit(yaw, pitch);
+            assert!((distance(&camera) - expected).abs() < 1e-9);
+            assert_eq!(camera.look_at, Vec3::from([0, 0, 0]));
+        }
+    }
+
+    #[test]
+    fn pan_moves_both_ends() {
+        let mut camera = CameraSettings::default();
+        let before = camera.clone();
+        camera.pan(0.5, -2.0);
+        let delta = camera.look_from - before.look_from;
+        assert!(close(&(camera.look_at - before.look_at), &delta));
+        assert!((delta.length() - 0.5f64.hypot(2.0)).abs() < 1e-9);
+        // in the plane of the image
+        let view = before.look_at - before.look_from;
+        assert!(delta.dot(&view).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zoom_stops_before_look_at() {
+        let mut camera = CameraSettings::default();
+        let direction = (camera.look_from - camera.look_at).unit();
+        let start = distance(&camera);
+        camera.zoom(0.5);
+        assert!((distance(&camera) - 0.5 * start).abs() < 1e-9);
+        for factor in [1e-9, 0.0, -1.0] {
+            for _ in 0..100 {
+                camera.zoom(factor);
+            }
+            assert!(distance(&camera) >= MIN_ZOOM_DISTANCE * (1.0 - 1e-9));
+            let still = (camera.look_from - camera.look_at).unit();
+            assert!(close(&still, &direction), "went through look_at");
+        }
+    }
+
+    #[test]
+    fn turn_stops_at_the_poles() {
+        for pitch in [0.05, -0.05] {
+            let mut camera = CameraSettings::default();
+            let heading = |camera: &CameraSettings| {
+                let offset = camera.look_from - camera.look_at;
+                Vec3::from([offset.x, 0.0, offset.z])
+            };
+            let start = heading(&camera);
+            for _ in 0..100 {
+                camera.orbit(0.0, pitch);
+            }
+            let offset = (camera.look_from - camera.look_at).unit();
+            let elevation = offset.dot(&camera.vup).abs();
+            assert!((0.98..0.99).contains(&elevation), "{elevation}");
+            // still on the same side, the camera didn't flip over
+            assert!(heading(&camera).dot(&start) > 0.0);
+
+            let mut camera = CameraSettings::default();
+            for _ in 0..100 {
+                camera.look_around(0.0, pitch);
+            }
+            let forward = (camera.look_at - camera.look_from).unit();
+            let elevation = forward.dot(&camera.vup).abs();
+            assert!((0.98..0.99).contains(&elevation), "{elevation}");
+        }
+    }
+
+    #[test]
+    fn focus_on_a_sphere() {
+        let sphere = |center: [f64; 3]| Sphere {
+            center: Vec3::from(center),
+            radius: 1.0,
+            material: Material::Lambertian {
+                albedo: Texture::Solid(Vec3::from([0.5, 0.5, 0.5])),
+            },
+        };
+        let camera = CameraSettings {
+            look_from: Vec3::from([0, 0, 0]),
+            look_at: Vec3::from([0, 0, -1]),
+            vfov: 90.0,
+            ..Default::default()
+        };
+        let size = (100, 100);
+
+        // in the middle of the image, the front of the sphere
+        let on_axis = camera.focus_distance(&sphere([0.0, 0.0, -5.0]), size, (50.0, 50.0));
+        assert!((on_axis.unwrap() - 4.0).abs() < 1e-9);
+        // nothing there
+        assert_eq!(
+            camera.focus_distance(&sphere([0.0, 0.0, -5.0]), size, (90.0, 10.0)),
+            None
+        );
+
+        // the center of the sphere is seen half way to the right edge, and
+        // its front is at sqrt(45) - 1 along the ray, which isn't the focus
+        // distance
+        let off_axis = camera.focus_distance(&sphere([3.0, 0.0, -6.0]), size, (75.0, 50.0));
+        let along_the_ray = 45f64.sqrt() - 1.0;
+        let expected = along_the_ray * 6.0 / 45f64.sqrt();
+        assert!((off_axis.unwrap() - expected).abs() < 1e-9, "{off_axis:?}");
+    }
 }
@@ -0,0 +1,333 @@
+//! Save the state of a progressive render to disk, so that it can be resumed
+//! later. The file is a small binary header with the render settings,
+//! followed by the accumulated samples of each pixel. Everything is little endian.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    camera::CameraSettings,
+    image_buffer::ImageBuffer,
+    sampler::SamplerKind,
+    vec3::{Color, Vec3},
+};
+
+const MAGIC: &[u8; 8] = b"RAYCKPT\0";
+const VERSION: u32 = 3;
+
+/// everything needed to render the same image of a scene again
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// seed used to generate random scenes, and to randomize the samples
+    pub seed: u64,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub sampler: SamplerKind,
+    pub camera: CameraSettings,
+}
+
+pub struct Checkpoint {
+    /// name of a builtin scene, or path to a scene file
+    pub scene: String,
+    pub settings: RenderSettings,
+    pub img_buffer: ImageBuffer,
+}
+
+impl Checkpoint {
+    /// write the checkpoint in a temporary file first, so that an interruption
+    /// in the middle of the save doesn't destroy the previous checkpoint
+    pub fn save(
+        path: &Path,
+        scene: &str,
+        settings: &RenderSettings,
+        img: &ImageBuffer,
+    ) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut w = BufWriter::new(File::create(&tmp)?);
+
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+
+        let scene = scene.as_bytes();
+        write_u64(&mut w, scene.len() as u64)?;
+        w.write_all(scene)?;
+        write_u64(&mut w, settings.seed)?;
+        write_u64(&mut w, settings.samples_per_pixel as u64)?;
+        write_u64(&mut w, settings.max_depth as u64)?;
+        let sampler = settings.sampler.name().as_bytes();
+        write_u64(&mut w, sampler.len() as u64)?;
+        w.write_all(sampler)?;
+
+        let cam = &settings.camera;
+        write_vec3(&mut w, &cam.look_from)?;
+        write_vec3(&mut w, &cam.look_at)?;
+        write_vec3(&mut w, &cam.vup)?;
+        write_f64(&mut w, cam.vfov)?;
+        write_f64(&mut w, cam.focal_length)?;
+        write_f64(&mut w, cam.aperture)?;
+        // NaN stands for the default focus distance
+        write_f64(&mut w, cam.focus_dist.unwrap_or(f64::NAN))?;
+        write_f64(&mut w, cam.shutter_open)?;
+        write_f64(&mut w, cam.shutter_close)?;
+
+        write_u64(&mut w, img.width as u64)?;
+        write_u64(&mut w, img.height as u64)?;
+        for (color, n) in img.pixels() {
+            write_vec3(&mut w, color)?;
+            write_u64(&mut w, *n as u64)?;
+        }
+
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// the lengths read from the file are checked against its size, so that
+    /// a corrupted file is reported instead of allocating huge buffers
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = std::fs::read(path)?;
+        let mut r = data.as_slice();
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint file".to_string()));
+        }
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported checkpoint version {version}, expected {VERSION}"
+            )));
+        }
+
+        let scene = read_string(&mut r)?;
+        let seed = read_u64(&mut r)?;
+        let samples_per_pixel = read_u64(&mut r)? as usize;
+        let max_depth = read_u64(&mut r)? as usize;
+        let sampler = read_string(&mut r)?;
+        let sampler = SamplerKind::from_name(&sampler)
+            .ok_or_else(|| invalid_data(format!("unknown sampler {sampler:?}")))?;
+
+        let camera = CameraSettings {
+            look_from: read_vec3(&mut r)?,
+            look_at: read_vec3(&mut r)?,
+            vup: read_vec3(&mut r)?,
+            vfov: read_f64(&mut r)?,
+            focal_length: read_f64(&mut r)?,
+            aperture: read_f64(&mut r)?,
+            focus_dist: Some(read_f64(&mut r)?).filter(|d| !d.is_nan()),
+            shutter_open: read_f64(&mut r)?,
+            shutter_close: read_f64(&mut r)?,
+        };
+
+        let width = read_u64(&mut r)? as usize;
+        let height = read_u64(&mut r)? as usize;
+        // a color and a sample count for each pixel
+        let expected = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(4 * 8))
+            .ok_or_else(|| invalid_data(format!("invalid image size {width}x{height}")))?;
+        if r.len() != expected {
+            return Err(invalid_data(format!(
+                "expected {expected} bytes of pixels for a {width}x{height} image, got {}",
+                r.len()
+            )));
+        }
+        let pixels = (0..width * height)
+            .map(|_| Ok((read_vec3(&mut r)?, read_u64(&mut r)? as usize)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            scene,
+            settings: RenderSettings {
+                seed,
+                samples_per_pixel,
+                max_depth,
+                sampler,
+                camera,
+            },
+            img_buffer: ImageBuffer::from_pixels(width, height, pixels),
+        })
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u64(w: &mut impl Write, x: u64) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+fn write_f64(w: &mut impl Write, x: f64) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+fn write_vec3(w: &mut impl Write, v: &Vec3) -> io::Result<()> {
+    write_f64(w, v.x)?;
+    write_f64(w, v.y)?;
+    write_f64(w, v.z)
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string(r: &mut &[u8]) -> io::Result<String> {
+    let len = read_u64(r)?;
+    if len > r.len() as u64 {
+        return Err(invalid_data(format!(
+            "string of {len} bytes, past the end of the file"
+        )));
+    }
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| invalid_data(format!("invalid string: {e}")))
+}
+
+fn read_f64(r: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+fn read_vec3(r: &mut impl Read) -> io::Result<Color> {
+    Ok(Vec3::from([read_f64(r)?, read_f64(r)?, read_f64(r)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a checkpoint file in the temporary directory, removed once dropped
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("raytracing-{}-{name}.ckpt", std::process::id());
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings {
+            seed: 42,
+            samples_per_pixel: 100,
+            max_depth: 12,
+            sampler: SamplerKind::Halton,
+            camera: CameraSettings {
+                look_from: Vec3::from([1, 2, 3]),
+                vfov: 35.0,
+                aperture: 0.2,
+                focus_dist: Some(4.5),
+                shutter_close: 0.5,
+                ..CameraSettings::default()
+            },
+        }
+    }
+
+    fn image() -> ImageBuffer {
+        let pixels = (0..6)
+            .map(|i| {
+                (
+                    Color::from([i as f64, 0.5 * i as f64, 1.0 / (i + 1) as f64]),
+                    i,
+                )
+            })
+            .collect();
+        ImageBuffer::from_pixels(3, 2, pixels)
+    }
+
+    fn save(name: &str) -> TempFile {
+        let file = TempFile::new(name);
+        Checkpoint::save(&file.0, "scenes/test.toml", &settings(), &image()).unwrap();
+        file
+    }
+
+    fn load_error(path: &Path) -> io::ErrorKind {
+        match Checkpoint::load(path) {
+            Ok(_) => panic!("the checkpoint should be rejected"),
+            Err(err) => err.kind(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = save("round-trip");
+        let checkpoint = Checkpoint::load(&file.0).unwrap();
+        assert_eq!(checkpoint.scene, "scenes/test.toml");
+        assert_eq!(checkpoint.settings, settings());
+        let img = checkpoint.img_buffer;
+        assert_eq!((img.width, img.height), (3, 2));
+        assert_eq!(img.pixels(), image().pixels());
+
+        // the default focus distance is kept as such
+        let mut settings = settings();
+        settings.camera.focus_dist = None;
+        Checkpoint::save(&file.0, "cornell", &settings, &image()).unwrap();
+        let checkpoint = Checkpoint::load(&file.0).unwrap();
+        assert_eq!(checkpoint.scene, "cornell");
+        assert_eq!(checkpoint.settings, settings);
+    }
+
+    #[test]
+    fn bad_header() {
+        let file = save("bad-header");
+        let data = std::fs::read(&file.0).unwrap();
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        std::fs::write(&file.0, bad_magic).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::InvalidData);
+
+        let mut bad_version = data;
+        bad_version[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        std::fs::write(&file.0, bad_version).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated() {
+        let file = save("truncated");
+        let data = std::fs::read(&file.0).unwrap();
+
+        // in the pixels
+        std::fs::write(&file.0, &data[..data.len() - 10]).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::InvalidData);
+        // in the header
+        std::fs::write(&file.0, &data[..40]).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn corrupted_lengths() {
+        let file = save("corrupted");
+        let data = std::fs::read(&file.0).unwrap();
+
+        // the length of the scene name, right after the header
+        let mut long_string = data.clone();
+        long_string[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&file.0, long_string).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::InvalidData);
+
+        // the image size, right before the 6 pixels
+        let size_offset = data.len() - 6 * 32 - 16;
+        let mut huge_image = data;
+        huge_image[size_offset..size_offset + 16].copy_from_slice(&[0xff; 16]);
+        std::fs::write(&file.0, huge_image).unwrap();
+        assert_eq!(load_error(&file.0), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,309 @@
+use std::{num::ParseIntError, path::PathBuf, str::FromStr, sync::Arc, time::Instant};
+
+use rand::random;
+
+use raytracing::{
+    load_scene, BackgroundWorker, Checkpoint, ImageBuffer, RenderSettings, SamplerKind, ToneMap,
+    ToneMapping, CHECKPOINT_INTERVAL, MAX_DEPTH, SAMPLES_PER_PIXEL,
+};
+
+use crate::{print_warnings, save_checkpoint};
+
+const USAGE: &str = "usage: raytracing render [--scene random|random-sky|lights|cornell|FILE] \
+    [--width W] [--height H] [--spp N] [--max-depth N] \
+    [--sampler independent|stratified|halton|sobol] [--seed N] \
+    [--checkpoint FILE] [--resume FILE] \
+    [--tonemap none|reinhard|extended-reinhard|aces|uncharted2] \
+    [--exposure STOPS] [--white-point W] --out FILE";
+
+/// options for a headless render, parsed from the command line
+#[derive(Debug)]
+pub(crate) struct RenderArgs {
+    pub(crate) scene: Option<String>,
+    pub(crate) width: Option<usize>,
+    pub(crate) height: Option<usize>,
+    pub(crate) samples_per_pixel: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) sampler: Option<SamplerKind>,
+    /// the same seed always gives the same image, random by default
+    pub(crate) seed: Option<u64>,
+    /// where to periodically save the progress of the render
+    pub(crate) checkpoint: Option<PathBuf>,
+    /// continue the render saved in this checkpoint
+    pub(crate) resume: Option<PathBuf>,
+    /// png or jpg for a displayable image, exr, hdr or pfm for the raw radiance
+    pub(crate) out: PathBuf,
+    /// only used for png and jpg outputs
+    pub(crate) tone_mapping: ToneMapping,
+}
+
+impl RenderArgs {
+    /// parse the arguments following the `render` subcommand
+    pub(crate) fn parse(args: &[String]) -> Result<Self, String> {
+        let mut scene = None;
+        let mut width = None;
+        let mut height = None;
+        let mut samples_per_pixel = None;
+        let mut max_depth = None;
+        let mut sampler = None;
+        let mut seed = None;
+        let mut checkpoint = None;
+        let mut resume = None;
+        let mut out = None;
+        let mut tone_mapping = ToneMapping::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "--scene" => scene = Some(value()?.clone()),
+                "--width" => width = Some(parse_number(arg, value()?)?),
+                "--height" => height = Some(parse_number(arg, value()?)?),
+                "--spp" => samples_per_pixel = Some(parse_number(arg, value()?)?),
+                "--max-depth" => max_depth = Some(parse_number(arg, value()?)?),
+                "--sampler" => {
+                    let name = value()?;
+                    sampler = Some(
+                        SamplerKind::from_name(name)
+                            .ok_or_else(|| format!("unknown sampler {name:?}\n{USAGE}"))?,
+                    );
+                }
+                "--seed" => seed = Some(parse_number(arg, value()?)?),
+                "--checkpoint" => checkpoint = Some(PathBuf::from(value()?)),
+                "--resume" => resume = Some(PathBuf::from(value()?)),
+                "--out" => out = Some(PathBuf::from(value()?)),
+                "--tonemap" => {
+                    let name = value()?;
+                    tone_mapping.operator = ToneMap::from_name(name)
+                        .ok_or_else(|| format!("unknown tone mapping {name:?}\n{USAGE}"))?;
+                }
+                "--exposure" => tone_mapping.exposure = parse_float(arg, value()?)?,
+                "--white-point" => tone_mapping.white_point = parse_float(arg, value()?)?,
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        let out = out.ok_or_else(|| format!("--out is required\n{USAGE}"))?;
+        if width == Some(0) || height == Some(0) {
+            return Err(format!("image dimensions must be positive\n{USAGE}"));
+        }
+        if resume.is_some()
+            && (scene.is_some()
+                || width.is_some()
+                || height.is_some()
+                || sampler.is_some()
+                || seed.is_some())
+        {
+            return Err(format!(
+                "the scene, image size, sampler and seed come from the checkpoint when resuming\n{USAGE}"
+            ));
+        }
+
+        Ok(Self {
+            scene,
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            sampler,
+            seed,
+            checkpoint,
+            resume,
+            out,
+            tone_mapping,
+        })
+    }
+}
+
+fn parse_number<T: FromStr<Err = ParseIntError>>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|e| format!("invalid value {value:?} for {arg}: {e}"))
+}
+
+fn parse_float(arg: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|e| format!("invalid value {value:?} for {arg}: {e}"))
+}
+
+/// render the world without opening any window, and write the result to disk
+pub(crate) fn render(args: &RenderArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+
+    let (name, settings, mut img_buffer, scene) = match &args.resume {
+        Some(path) => {
+            let Checkpoint {
+                scene: name,
+                mut settings,
+                img_buffer,
+            } = Checkpoint::load(path)?;
+            settings.samples_per_pixel =
+                args.samples_per_pixel.unwrap_or(settings.samples_per_pixel);
+            settings.max_depth = args.max_depth.unwrap_or(settings.max_depth);
+            let scene = load_scene(&name, settings.seed)?;
+            print_warnings(&scene);
+            (name, settings, img_buffer, scene)
+        }
+        None => {
+            let name = args.scene.clone().unwrap_or_else(|| "random".to_string());
+            let seed = args.seed.unwrap_or_else(|| {
+                let seed = random();
+                println!("seed {seed}");
+                seed
+            });
+            let mut scene = load_scene(&name, seed)?;
+            print_warnings(&scene);
+            // the size given on the command line takes precedence
+            scene.image_width = args.width.or(scene.image_width);
+            if let Some(height) = args.height {
+                let (width, _) = scene.image_size();
+                scene.aspect_ratio = Some(width as f64 / height as f64);
+            }
+            let (width, height) = scene.image_size();
+            let settings = RenderSettings {
+                seed,
+                samples_per_pixel: args.samples_per_pixel.unwrap_or(SAMPLES_PER_PIXEL),
+                max_depth: args.max_depth.unwrap_or(MAX_DEPTH),
+                sampler: args.sampler.unwrap_or(SamplerKind::Sobol),
+                camera: scene.camera.clone(),
+            };
+            (name, settings, ImageBuffer::new(width, height), scene)
+        }
+    };
+
+    let camera = settings
+        .camera
+        .to_camera(img_buffer.width, img_buffer.height);
+    let already_done = img_buffer.min_samples();
+    if already_done > 0 {
+        println!("resuming from {already_done} samples per pixel");
+    }
+    let bgw = BackgroundWorker {
+        samples_per_pixel: settings.samples_per_pixel,
+        max_depth: settings.max_depth,
+        sampler: settings.sampler,
+        seed: settings.seed,
+    };
+
+    let checkpoint_path = args.checkpoint.as_deref().or(args.resume.as_deref());
+    let mut last_checkpoint = Instant::now();
+    let pixel_count = camera.image_width * camera.image_height;
+    // samples of all the pixels together, to report the progress once every
+    // pixel has got some more. A checkpoint can have more samples in some
+    // tiles than in others.
+    let mut pixel_samples = img_buffer.pixels().iter().map(|(_, n)| n).sum::<usize>();
+    let mut reported = already_done;
+    // the channel is closed once all the samples have been computed
+    for tile in bgw.start(Arc::new(scene.world), &camera, &img_buffer) {
+        img_buffer.add_tile(&tile);
+        pixel_samples += tile.width * tile.height * tile.samples;
+        let done = pixel_samples / pixel_count.max(1);
+        if done > reported {
+            reported = done;
+            eprint!(
+                "\rsample {}/{} ({:.1}s)",
+                done,
+                settings.samples_per_pixel,
+                start.elapsed().as_secs_f64()
+            );
+            if last_checkpoint.elapsed() > CHECKPOINT_INTERVAL {
+                last_checkpoint = Instant::now();
+                save_checkpoint(checkpoint_path, &name, &settings, &img_buffer);
+            }
+        }
+    }
+    eprintln!();
+    println!(
+        "image took {}ms with {} samples per pixels with at most {} reflections ({} sampler)",
+        start.elapsed().as_millis(),
+        settings.samples_per_pixel,
+        settings.max_depth,
+        settings.sampler
+    );
+
+    save_checkpoint(checkpoint_path, &name, &settings, &img_buffer);
+    img_buffer.save(&args.out, &args.tone_mapping)?;
+    println!("image written to {}", args.out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<RenderArgs, String> {
+        let args = args
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        RenderArgs::parse(&args)
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let resuming = "the scene, image size, sampler and seed come from the checkpoint";
+        for (args, error) in [
+            ("--width 100", "--out is required"),
+            ("--out a.png --width", "missing value for --width"),
+            ("--out a.png --fast", "unknown argument --fast"),
+            ("--out a.png --width 0", "image dimensions must be positive"),
+            (
+                "--out a.png --height 0",
+                "image dimensions must be positive",
+            ),
+            (
+                "--out a.png --width ten",
+                "invalid value \"ten\" for --width",
+            ),
+            ("--out a.png --sampler random", "unknown sampler \"random\""),
+            ("--out a.png --resume c.ckpt --scene cornell", resuming),
+            ("--out a.png --resume c.ckpt --seed 3", resuming),
+            ("--out a.png --resume c.ckpt --width 100", resuming),
+            ("--out a.png --resume c.ckpt --sampler halton", resuming),
+        ] {
+            match parse(args) {
+                Ok(_) => panic!("{args:?} should be rejected"),
+                Err(err) => assert!(err.starts_with(error), "{args:?} gave {err:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn all_arguments() {
+        let args = parse(
+            "--scene cornell --width 640 --height 480 --spp 64 --max-depth 8 \
+             --sampler halton --seed 7 --checkpoint c.ckpt --tonemap aces \
+             --exposure -1.5 --white-point 6 --out image.exr",
+        )
+        .unwrap();
+        assert_eq!(args.scene.as_deref(), Some("cornell"));
+        assert_eq!((args.width, args.height), (Some(640), Some(480)));
+        assert_eq!(
+            (args.samples_per_pixel, args.max_depth),
+            (Some(64), Some(8))
+        );
+        assert_eq!(args.sampler, Some(SamplerKind::Halton));
+        assert_eq!(args.seed, Some(7));
+        assert_eq!(args.checkpoint, Some(PathBuf::from("c.ckpt")));
+        assert_eq!(args.resume, None);
+        assert_eq!(args.out, PathBuf::from("image.exr"));
+        let tone_mapping = ToneMapping {
+            operator: ToneMap::Aces,
+            exposure: -1.5,
+            white_point: 6.0,
+        };
+        assert_eq!(args.tone_mapping, tone_mapping);
+
+        // only the number of samples and depth can change when resuming
+        let args = parse("--resume c.ckpt --spp 128 --max-depth 4 --out b.png").unwrap();
+        assert_eq!(args.resume, Some(PathBuf::from("c.ckpt")));
+        assert_eq!(
+            (args.samples_per_pixel, args.max_depth),
+            (Some(128), Some(4))
+        );
+    }
+}
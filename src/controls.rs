@@ -0,0 +1,182 @@
+//! Moving the camera around from the GUI. The functions taking the camera
+//! return whether it changed, in which case the render must start over.
+
+use eframe::egui::{self, Key, PointerButton};
+
+use raytracing::{CameraSettings, SamplerKind, ToneMap, ToneMapping, World};
+
+/// radians per pixel of mouse movement
+const ORBIT_SPEED: f64 = 0.005;
+
+/// radians per second when looking around with the arrow keys
+const LOOK_SPEED: f64 = 1.0;
+
+/// how much one "notch" of the mouse wheel zooms in
+const ZOOM_SPEED: f64 = 0.002;
+
+/// fraction of the distance to look_at travelled per second in fly mode
+const FLY_SPEED: f64 = 0.5;
+
+/// sliders for the camera settings which aren't controlled with the mouse
+pub(crate) fn settings_panel(ui: &mut egui::Ui, camera: &mut CameraSettings) -> bool {
+    let mut changed = false;
+    ui.heading("Camera");
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut camera.vfov, 1.0..=150.0)
+                .text("vertical fov")
+                .suffix("°"),
+        )
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut camera.aperture, 0.0..=2.0).text("aperture"))
+        .changed();
+
+    let mut auto_focus = camera.focus_dist.is_none();
+    if ui.checkbox(&mut auto_focus, "focus on look_at").changed() {
+        changed = true;
+        camera.focus_dist = if auto_focus {
+            None
+        } else {
+            Some((camera.look_from - camera.look_at).length())
+        };
+    }
+    if let Some(focus_dist) = &mut camera.focus_dist {
+        changed |= ui
+            .add(
+                egui::Slider::new(focus_dist, 0.1..=1000.0)
+                    .logarithmic(true)
+                    .text("focus distance"),
+            )
+            .changed();
+    }
+
+    ui.separator();
+    ui.label("click: focus\ndrag: orbit\nright or middle drag: pan\nscroll: zoom");
+    ui.label("WASD: fly, Q/E: down/up\narrows: look around");
+    changed
+}
+
+/// how the image is displayed, this doesn't need to restart the render
+pub(crate) fn tone_mapping_panel(ui: &mut egui::Ui, tone_mapping: &mut ToneMapping) {
+    ui.heading("Tone mapping");
+    egui::ComboBox::from_label("operator")
+        .selected_text(tone_mapping.operator.name())
+        .show_ui(ui, |ui| {
+            for op in ToneMap::ALL {
+                ui.selectable_value(&mut tone_mapping.operator, op, op.name());
+            }
+        });
+    ui.add(
+        egui::Slider::new(&mut tone_mapping.exposure, -8.0..=8.0)
+            .text("exposure")
+            .suffix(" stops"),
+    );
+    if tone_mapping.operator == ToneMap::ExtendedReinhard {
+        ui.add(
+            egui::Slider::new(&mut tone_mapping.white_point, 0.1..=100.0)
+                .logarithmic(true)
+                .text("white point"),
+        );
+    }
+}
+
+/// returns true if the sampler changed, in which case the image has to be
+/// rendered again
+pub(crate) fn sampler_panel(ui: &mut egui::Ui, sampler: &mut SamplerKind) -> bool {
+    let before = *sampler;
+    ui.heading("Sampling");
+    egui::ComboBox::from_label("sampler")
+        .selected_text(sampler.name())
+        .show_ui(ui, |ui| {
+            for kind in SamplerKind::ALL {
+                ui.selectable_value(sampler, kind, kind.name());
+            }
+        });
+    *sampler != before
+}
+
+/// mouse controls over the image, and keyboard controls when no widget
+/// is using the keyboard
+pub(crate) fn handle_input(
+    ctx: &egui::Context,
+    response: &egui::Response,
+    camera: &mut CameraSettings,
+) -> bool {
+    let mut changed = false;
+
+    let delta = response.drag_delta();
+    if delta != egui::Vec2::ZERO {
+        let (dx, dy) = (delta.x as f64, delta.y as f64);
+        if response.dragged_by(PointerButton::Primary) {
+            camera.orbit(-dx * ORBIT_SPEED, -dy * ORBIT_SPEED);
+            changed = true;
+        } else if response.dragged_by(PointerButton::Secondary)
+            || response.dragged_by(PointerButton::Middle)
+        {
+            // the point under the cursor (at the look_at distance) should
+            // follow the cursor
+            let pixel_size = camera.pixel_size(response.rect.height() as usize);
+            camera.pan(-dx * pixel_size, dy * pixel_size);
+            changed = true;
+        }
+    }
+
+    if response.hovered() {
+        let scroll = ctx.input(|i| i.scroll_delta.y) as f64;
+        if scroll != 0.0 {
+            camera.zoom((-scroll * ZOOM_SPEED).exp());
+            changed = true;
+        }
+    }
+
+    if ctx.wants_keyboard_input() {
+        return changed;
+    }
+    let (dt, axis) = ctx.input(|i| {
+        let axis = |neg: Key, pos: Key| i.key_down(pos) as i8 as f64 - i.key_down(neg) as i8 as f64;
+        (
+            i.stable_dt as f64,
+            [
+                axis(Key::S, Key::W),
+                axis(Key::A, Key::D),
+                axis(Key::Q, Key::E),
+                axis(Key::ArrowRight, Key::ArrowLeft),
+                axis(Key::ArrowDown, Key::ArrowUp),
+            ],
+        )
+    });
+    let [forward, right, up, yaw, pitch] = axis;
+    if forward != 0.0 || right != 0.0 || up != 0.0 {
+        let speed = FLY_SPEED * (camera.look_from - camera.look_at).length() * dt;
+        camera.fly(forward * speed, right * speed, up * speed);
+        changed = true;
+    }
+    if yaw != 0.0 || pitch != 0.0 {
+        camera.look_around(yaw * LOOK_SPEED * dt, pitch * LOOK_SPEED * dt);
+        changed = true;
+    }
+    changed
+}
+
+/// clicking on the image puts the object under the cursor in focus
+pub(crate) fn focus_on_click(
+    world: &World,
+    response: &egui::Response,
+    camera: &mut CameraSettings,
+) -> bool {
+    if !response.clicked() {
+        return false;
+    }
+    let Some(pos) = response.interact_pointer_pos() else {
+        return false;
+    };
+    let rect = response.rect;
+    let size = (rect.width() as usize, rect.height() as usize);
+    let pixel = ((pos.x - rect.left()) as f64, (pos.y - rect.top()) as f64);
+    let Some(focus_dist) = camera.focus_distance(world, size, pixel) else {
+        return false;
+    };
+    camera.focus_dist = Some(focus_dist);
+    true
+}
@@ -0,0 +1,301 @@
+//! Environment maps: a lat-long (equirectangular) image surrounding the
+//! scene, lighting it from every direction. Directions are picked according
+//! to the luminance of the map, so that bright spots like the sun are found
+//! by the light sampling.
+
+use std::{f64::consts::PI, fs::File, io::BufReader, path::Path};
+
+use image::{codecs::hdr::HdrDecoder, DynamicImage};
+
+use crate::{
+    tonemap::srgb_eotf,
+    vec3::{Color, Vec3},
+};
+
+/// discrete distribution over the indices of `weights`
+#[derive(Debug)]
+struct Distribution {
+    /// cumulative weights, cdf[i] is the sum of the weights before i
+    cdf: Vec<f64>,
+}
+
+impl Distribution {
+    fn new(weights: impl Iterator<Item = f64>) -> Self {
+        let mut cdf = vec![0.0];
+        let mut sum = 0.0;
+        for w in weights {
+            sum += w;
+            cdf.push(sum);
+        }
+        Self { cdf }
+    }
+
+    fn total(&self) -> f64 {
+        self.cdf[self.cdf.len() - 1]
+    }
+
+    /// index picked for `x` in [0;1[, and where `x` falls between the
+    /// boundaries of that index, in [0;1[, to be reused as another sample
+    fn sample(&self, x: f64) -> (usize, f64) {
+        let target = x * self.total();
+        // first index whose cumulated weight reaches the target, skipping the
+        // ones which can't be picked
+        let idx = self.cdf.partition_point(|c| *c <= target);
+        let idx = idx.clamp(1, self.cdf.len() - 1) - 1;
+        let offset = (target - self.cdf[idx]) / self.weight(idx);
+        (idx, offset.clamp(0.0, 1.0 - f64::EPSILON))
+    }
+
+    fn weight(&self, idx: usize) -> f64 {
+        self.cdf[idx + 1] - self.cdf[idx]
+    }
+}
+
+/// the center of the image is towards -z, and its left edge towards +z
+/// after going around through -x. The top row is straight up (+y).
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// linear colors, row by row starting from the top of the image
+    pixels: Vec<Color>,
+    /// rotation around the y axis, in radians
+    rotation: f64,
+    intensity: f64,
+    /// picks a row, and then a column in that row
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+impl EnvironmentMap {
+    /// floating point images (HDR, EXR) are used as is, other ones are
+    /// considered sRGB encoded. The rotation is in degrees, counter clockwise
+    /// seen from above.
+    pub fn load(path: &Path, rotation: f64, intensity: f64) -> image::ImageResult<Self> {
+        let is_hdr = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        let rgb = |px: &[f32]| Color::from([px[0] as f64, px[1] as f64, px[2] as f64]);
+        let (width, height, pixels) = if is_hdr {
+            // `image::open` converts Radiance HDR files to 8 bits
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let meta = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|px| rgb(&px.0))
+                .collect::<Vec<_>>();
+            (meta.width, meta.height, pixels)
+        } else {
+            let img = image::open(path)?;
+            let linear = matches!(
+                img,
+                DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+            );
+            let img = img.into_rgb32f();
+            let pixels = img
+                .pixels()
+                .map(|px| {
+                    if linear {
+                        rgb(&px.0)
+                    } else {
+                        rgb(&px.0).map(srgb_eotf)
+                    }
+                })
+                .collect();
+            (img.width(), img.height(), pixels)
+        };
+        let pixels = pixels.into_iter().map(|c| c.map(|c| c.max(0.0))).collect();
+        Ok(Self::new(
+            width as usize,
+            height as usize,
+            pixels,
+            rotation,
+            intensity,
+        ))
+    }
+
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+        rotation: f64,
+        intensity: f64,
+    ) -> Self {
+        // the rows near the poles cover a smaller solid angle
+        let columns = pixels
+            .chunks(width.max(1))
+            .enumerate()
+            .map(|(row, colors)| {
+                let sin = (PI * (row as f64 + 0.5) / height as f64).sin();
+                Distribution::new(colors.iter().map(|c| luminance(c) * sin))
+            })
+            .collect::<Vec<_>>();
+        let rows = Distribution::new(columns.iter().map(Distribution::total));
+        Self {
+            width,
+            height,
+            pixels,
+            rotation: rotation * PI / 180.0,
+            intensity,
+            rows,
+            columns,
+        }
+    }
+
+    /// whether there is any light to sample
+    pub fn is_black(&self) -> bool {
+        self.rows.total() <= 0.0
+    }
+
+    /// coordinates in the image, in [0;1], of a direction
+    fn uv(&self, dir: &Vec3) -> (f64, f64) {
+        let d = rotate_y(&dir.unit(), -self.rotation);
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = 2.0 * PI * (u - 0.5);
+        let theta = PI * v;
+        let d = Vec3::from([
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        ]);
+        rotate_y(&d, self.rotation)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let col = ((u * self.width as f64) as usize).min(self.width - 1);
+        let row = ((v * self.height as f64) as usize).min(self.height - 1);
+        (col, row)
+    }
+
+    /// light coming from the direction `dir`
+    pub fn color(&self, dir: &Vec3) -> Color {
+        if self.pixels.is_empty() {
+            return Color::default();
+        }
+        let (u, v) = self.uv(dir);
+        let (col, row) = self.pixel(u, v);
+        self.intensity * self.pixels[row * self.width + col]
+    }
+
+    /// direction for a point `u` of the unit square, more likely towards the
+    /// bright parts of the map
+    pub fn sample(&self, (x, y): (f64, f64)) -> Vec3 {
+        let (row, dv) = self.rows.sample(y);
+        let (col, du) = self.columns[row].sample(x);
+        let u = (col as f64 + du) / self.width as f64;
+        let v = (row as f64 + dv) / self.height as f64;
+        self.direction(u, v)
+    }
+
+    /// density (with respect to solid angle) with which `sample` returns `dir`
+    pub fn pdf(&self, dir: &Vec3) -> f64 {
+        if self.is_black() {
+            return 0.0;
+        }
+        let (u, v) = self.uv(dir);
+        let (col, row) = self.pixel(u, v);
+        let sin = (PI * v).sin();
+        if sin <= 0.0 {
+            return 0.0;
+        }
+        let prob = self.columns[row].weight(col) / self.rows.total();
+        // uniform within the pixel, which covers 2π²sin(θ)/(width*height)
+        // steradians
+        prob * (self.width * self.height) as f64 / (2.0 * PI * PI * sin)
+    }
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn rotate_y(v: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::from([cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{Independent, Sampler};
+
+    /// a small map with a bright spot, rotated so that nothing lines up
+    fn map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let (col, row) = (i % width, i / width);
+                if (col, row) == (5, 2) {
+                    Color::from([50, 40, 30])
+                } else {
+                    Color::from([0.2, 0.3, 0.5 + 0.1 * row as f64])
+                }
+            })
+            .collect();
+        EnvironmentMap::new(width, height, pixels, 30.0, 2.0)
+    }
+
+    #[test]
+    fn directions_round_trip() {
+        let map = map();
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)] {
+            let (u2, v2) = map.uv(&map.direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+    }
+
+    /// integral of `f` over all the directions
+    fn integrate(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (n_theta, n_phi) = (400, 800);
+        let (dtheta, dphi) = (PI / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut total = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * dtheta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * dphi;
+                let dir = Vec3::from([
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ]);
+                total += f(&dir) * theta.sin() * dtheta * dphi;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = map();
+        let total = integrate(|dir| map.pdf(dir));
+        assert!((total - 1.0).abs() < 1e-2, "pdf integrates to {total}");
+    }
+
+    #[test]
+    fn importance_sampling_estimates_the_irradiance() {
+        let map = map();
+        // light arriving on an upward facing surface
+        let expected = integrate(|dir| luminance(&map.color(dir)) * dir.y.max(0.0));
+        let n = 200_000;
+        let mut sampler = Independent::new(7);
+        let mut estimate = 0.0;
+        for _ in 0..n {
+            let dir = map.sample(sampler.next_2d());
+            if dir.y > 0.0 {
+                estimate += luminance(&map.color(&dir)) * dir.y / map.pdf(&dir);
+            }
+        }
+        estimate /= n as f64;
+        assert!(
+            (estimate - expected).abs() < 0.02 * expected,
+            "{estimate} != {expected}"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[cfg(feature = "viewer")]
+use eframe::egui::{Color32, ColorImage};
+#[cfg(feature = "viewer")]
+use egui_extras::RetainedImage;
+
+use crate::{tonemap::ToneMapping, vec3::Color};
+
+/// side of the square tiles the image is split into when rendering
+const TILE_SIZE: usize = 32;
+
+/// samples for a rectangle of the image, rendered together so that they can
+/// be sent and added to the image at once
+#[derive(Debug, Clone)]
+pub struct Tile {
+    /// top left corner, the rows going down from the top of the image
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// how many samples each pixel has received
+    pub samples: usize,
+    /// sum of the samples of each pixel, row by row
+    pub pixels: Vec<Color>,
+}
+
+impl Tile {
+    /// empty tiles covering an image
+    pub fn grid(width: usize, height: usize) -> Vec<Tile> {
+        let mut tiles = vec![];
+        for y in (0..height).step_by(TILE_SIZE) {
+            for x in (0..width).step_by(TILE_SIZE) {
+                let w = TILE_SIZE.min(width - x);
+                let h = TILE_SIZE.min(height - y);
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: w,
+                    height: h,
+                    samples: 0,
+                    pixels: vec![Color::default(); w * h],
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// accumulate the samples for each pixel, so that the image can be
+/// refined progressively.
+pub struct ImageBuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<(Color, usize)>,
+}
+
+impl ImageBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        let pixels = std::iter::repeat_n((Color::default(), 0), width * height).collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// rebuild a buffer from previously accumulated samples, see `pixels`
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<(Color, usize)>) -> Self {
+        assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// sum of the samples and number of samples for each pixel, row by row
+    /// starting from the top of the image
+    pub fn pixels(&self) -> &[(Color, usize)] {
+        &self.pixels
+    }
+
+    /// how many samples every pixel has received at least
+    pub fn min_samples(&self) -> usize {
+        self.pixels.iter().map(|(_, n)| *n).min().unwrap_or(0)
+    }
+
+    /// how many samples every pixel of the tile has received at least
+    pub(crate) fn tile_samples(&self, tile: &Tile) -> usize {
+        (0..tile.height)
+            .flat_map(|row| {
+                let start = (tile.y + row) * self.width + tile.x;
+                &self.pixels[start..start + tile.width]
+            })
+            .map(|(_, n)| *n)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn add_tile(&mut self, tile: &Tile) {
+        for (row, colors) in tile.pixels.chunks(tile.width).enumerate() {
+            let start = (tile.y + row) * self.width + tile.x;
+            for ((c, n), color) in self.pixels[start..start + tile.width]
+                .iter_mut()
+                .zip(colors)
+            {
+                *c += *color;
+                *n += tile.samples;
+            }
+        }
+    }
+
+    /// average the samples of each pixel, giving the linear radiance
+    fn averaged(&self) -> impl Iterator<Item = Color> + '_ {
+        self.pixels.iter().map(|(col, n)| {
+            if *n == 0 {
+                Color::default()
+            } else {
+                col / (*n as f64)
+            }
+        })
+    }
+
+    /// average the samples of each pixel and make them displayable
+    #[cfg(feature = "viewer")]
+    fn to_color32(&self, tone_mapping: &ToneMapping) -> Vec<Color32> {
+        self.averaged()
+            .map(|color| tone_mapping.to_color32(color))
+            .collect()
+    }
+
+    #[cfg(feature = "viewer")]
+    pub fn to_retained_image(&self, tone_mapping: &ToneMapping) -> RetainedImage {
+        let img = ColorImage {
+            size: [self.width, self.height],
+            pixels: self.to_color32(tone_mapping),
+        };
+        RetainedImage::from_color_image("", img)
+    }
+
+    /// write the image to disk, the format is deduced from the extension.
+    /// `.exr`, `.hdr` and `.pfm` files get the linear radiance as floats,
+    /// without any gamma correction nor clamping. The tone mapping is only
+    /// used for the other formats.
+    pub fn save(&self, path: &Path, tone_mapping: &ToneMapping) -> image::ImageResult<()> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("exr") => self.to_rgb32f().save(path),
+            Some("hdr") => {
+                let pixels = self.to_rgb32f().pixels().copied().collect::<Vec<_>>();
+                let out = BufWriter::new(File::create(path)?);
+                image::codecs::hdr::HdrEncoder::new(out).encode(&pixels, self.width, self.height)
+            }
+            Some("pfm") => Ok(self.write_pfm(path)?),
+            _ => {
+                let raw = self
+                    .averaged()
+                    .flat_map(|color| tone_mapping.to_rgb8(color))
+                    .collect::<Vec<_>>();
+                let img = image::RgbImage::from_raw(self.width as u32, self.height as u32, raw)
+                    .expect("buffer size doesn't match image dimensions");
+                img.save(path)
+            }
+        }
+    }
+
+    fn to_rgb32f(&self) -> image::Rgb32FImage {
+        let raw = self
+            .averaged()
+            .flat_map(|c| [c.x as f32, c.y as f32, c.z as f32])
+            .collect::<Vec<_>>();
+        image::Rgb32FImage::from_raw(self.width as u32, self.height as u32, raw)
+            .expect("buffer size doesn't match image dimensions")
+    }
+
+    /// portable float map: a small text header followed by the raw floats,
+    /// with the rows going from the bottom to the top of the image. A negative
+    /// scale means little endian.
+    fn write_pfm(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let colors = self.averaged().collect::<Vec<_>>();
+        for row in colors.chunks(self.width.max(1)).rev() {
+            for c in row {
+                for component in [c.x, c.y, c.z] {
+                    out.write_all(&(component as f32).to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// removed with everything in it once the test is done
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let name = format!("raytracing-{}-{name}", std::process::id());
+            let dir = std::env::temp_dir().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// a different color for each pixel, with different numbers of samples,
+    /// chosen so that the averages are exact in f32
+    fn color_of(x: usize, y: usize) -> Color {
+        Color::from([0.25 * x as f64, 0.5 * y as f64 + 0.125, (x + 10 * y) as f64])
+    }
+
+    fn image(width: usize, height: usize) -> ImageBuffer {
+        let pixels = (0..width * height)
+            .map(|i| {
+                let n = 1 + i % 3;
+                (color_of(i % width, i / width) * n as f64, n)
+            })
+            .collect();
+        ImageBuffer::from_pixels(width, height, pixels)
+    }
+
+    fn f32s(c: Color) -> [f32; 3] {
+        [c.x as f32, c.y as f32, c.z as f32]
+    }
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let (width, height) = (70, 33);
+        let mut covered = vec![0; width * height];
+        for tile in Tile::grid(width, height) {
+            assert!(tile.width > 0 && tile.height > 0);
+            assert_eq!(tile.pixels.len(), tile.width * tile.height);
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    covered[y * width + x] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn add_edge_tile() {
+        let (width, height) = (70, 33);
+        let mut tile = Tile::grid(width, height).pop().unwrap();
+        // the bottom right corner, smaller than the others
+        assert_eq!((tile.x, tile.y, tile.width, tile.height), (64, 32, 6, 1));
+        tile.samples = 2;
+        for (i, pixel) in tile.pixels.iter_mut().enumerate() {
+            *pixel = Color::from([i as f64 + 1.0, 0.0, 0.0]);
+        }
+
+        let mut img = ImageBuffer::new(width, height);
+        img.add_tile(&tile);
+        for (i, (color, n)) in img.pixels().iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            if y == 32 && x >= 64 {
+                assert_eq!((color.x, *n), ((x - 64) as f64 + 1.0, 2), "pixel {x}, {y}");
+            } else {
+                assert_eq!((color.x, *n), (0.0, 0), "pixel {x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let dir = TempDir::new("pfm");
+        let path = dir.0.join("image.pfm");
+        let (width, height) = (5, 3);
+        image(width, height)
+            .save(&path, &ToneMapping::default())
+            .unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let header = "PF\n5 3\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header.as_bytes());
+        let floats = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(floats.len(), width * height * 3);
+        // the rows go from the bottom to the top
+        for (i, rgb) in floats.chunks(3).enumerate() {
+            let (x, y) = (i % width, height - 1 - i / width);
+            assert_eq!(rgb, f32s(color_of(x, y)), "pixel {x}, {y}");
+        }
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let dir = TempDir::new("exr");
+        let path = dir.0.join("image.exr");
+        let (width, height) = (5, 3);
+        image(width, height)
+            .save(&path, &ToneMapping::default())
+            .unwrap();
+
+        let read = image::open(&path).unwrap().into_rgb32f();
+        assert_eq!(read.dimensions(), (width as u32, height as u32));
+        for (x, y, rgb) in read.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            assert_eq!(rgb.0, f32s(color_of(x, y)), "pixel {x}, {y}");
+        }
+    }
+}
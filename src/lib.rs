@@ -0,0 +1,678 @@
+//! A path tracer: scenes made of spheres, triangle meshes, quads and
+//! volumes, lit by emissive objects, environment maps or a daylight sky.
+//!
+//! A scene is either built in code, from a [`World`] and a
+//! [`CameraSettings`], or loaded from a TOML file with [`load_scene`]. It is
+//! then rendered with [`render`], or progressively in the background with a
+//! [`BackgroundWorker`].
+
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use rand::{distributions::Uniform, rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::prelude::*;
+
+mod aabb;
+mod bvh;
+mod camera;
+mod checkpoint;
+mod environment;
+mod image_buffer;
+mod light;
+mod material;
+mod mesh;
+mod obj;
+mod quad;
+mod ray;
+mod sampler;
+mod scene;
+mod sky;
+mod texture;
+mod tonemap;
+mod transform;
+mod vec3;
+
+pub use aabb::Aabb;
+pub use camera::{Camera, CameraSettings};
+pub use checkpoint::{Checkpoint, RenderSettings};
+pub use environment::EnvironmentMap;
+pub use image_buffer::{ImageBuffer, Tile};
+pub use light::Light;
+pub use material::{BsdfSample, ConstantMedium, Material, MovingSphere, Sphere};
+pub use mesh::Triangle;
+pub use quad::{BoxShape, Plane, Quad};
+pub use ray::{Face, HitRecord, Hittable, Object, Ray};
+pub use sampler::{Independent, Sampler, SamplerKind};
+pub use scene::{Scene, SceneError, DEFAULT_ASPECT_RATIO, DEFAULT_IMAGE_WIDTH};
+pub use sky::Sky;
+pub use texture::{ImageTexture, Perlin, Texture};
+pub use tonemap::{ToneMap, ToneMapping};
+pub use transform::{Transform, Transformed};
+pub use vec3::{Color, Point3, Vec3};
+
+use bvh::Bvh;
+use light::LightList;
+/// how many ray per pixels (and its neighborhood)
+pub const SAMPLES_PER_PIXEL: usize = 50;
+
+/// how many maximum bounce for rays before we give up and return black.
+/// Paths are normally stopped well before by russian roulette.
+pub const MAX_DEPTH: usize = 100;
+
+/// number of bounces before paths can be randomly stopped
+const RUSSIAN_ROULETTE_DEPTH: usize = 3;
+
+/// how often the progress of a render is saved, when checkpoints are enabled
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// what a ray sees when it doesn't hit anything
+#[derive(Debug, Clone)]
+pub enum Background {
+    /// yellow to blue gradient, acting as a big light surrounding the scene
+    Gradient,
+    /// use black for scenes which should only be lit by emissive materials
+    Solid(Color),
+    /// an image all around the scene, which is sampled like the other lights
+    Environment(Arc<EnvironmentMap>),
+    /// daylight, with the sun sampled like the other lights
+    Sky(Arc<Sky>),
+}
+
+impl Background {
+    fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Gradient => {
+                let unit_direction = ray.dir.unit();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Color::from([1, 1, 0]) + t * Color::from([0.5, 0.7, 1.0])
+            }
+            Background::Solid(color) => *color,
+            Background::Environment(map) => map.color(&ray.dir),
+            Background::Sky(sky) => sky.color(&ray.dir),
+        }
+    }
+
+    fn light(&self) -> Option<Light> {
+        match self {
+            Background::Environment(map) if !map.is_black() => {
+                Some(Light::Environment(map.clone()))
+            }
+            Background::Sky(sky) => sky.light(),
+            _ => None,
+        }
+    }
+}
+
+/// everything in the scene, and what surrounds it
+pub struct World {
+    objects: Bvh<Object>,
+    /// the emissive objects, to sample them directly
+    lights: LightList,
+    background: Background,
+}
+
+impl World {
+    pub fn new(objects: Vec<Object>) -> Self {
+        let lights = objects.iter().flat_map(Hittable::lights).collect();
+        World {
+            objects: Bvh::new(objects),
+            lights: LightList::new(lights),
+            background: Background::Gradient,
+        }
+    }
+
+    pub fn from_spheres(spheres: Vec<Sphere>) -> Self {
+        Self::new(
+            spheres
+                .into_iter()
+                .map(|sphere| Box::new(sphere) as Object)
+                .collect(),
+        )
+    }
+
+    pub fn with_background(self, background: Background) -> Self {
+        let mut lights = self.objects.lights();
+        lights.extend(background.light());
+        World {
+            lights: LightList::new(lights),
+            background,
+            ..self
+        }
+    }
+
+    /// a few spheres in the dark, only lit by a glowing sphere above them
+    fn new_lights() -> Self {
+        let spheres = vec![
+            Sphere {
+                center: Vec3::from([0, -1000, 0]),
+                radius: 1000.0,
+                material: Material::Lambertian {
+                    albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+                },
+            },
+            Sphere {
+                center: Vec3::from([0, 1, 0]),
+                radius: 1.0,
+                material: Material::Dielectric { ir: 1.5 },
+            },
+            Sphere {
+                center: Vec3::from([-4, 1, 0]),
+                radius: 1.0,
+                material: Material::Lambertian {
+                    albedo: Texture::Solid(Color::from([0.5, 0.2, 0.1])),
+                },
+            },
+            Sphere {
+                center: Vec3::from([4, 1, 0]),
+                radius: 1.0,
+                material: Material::Metal {
+                    albedo: Color::from([0.7, 0.6, 0.5]),
+                    fuzz: 0.0,
+                },
+            },
+            Sphere {
+                center: Vec3::from([0.0, 3.2, 0.0]),
+                radius: 1.0,
+                material: Material::DiffuseLight {
+                    emit: Color::from([4, 4, 4]),
+                },
+            },
+            Sphere {
+                center: Vec3::from([2.0, 0.3, 1.5]),
+                radius: 0.3,
+                material: Material::DiffuseLight {
+                    emit: Color::from([4.0, 1.0, 0.5]),
+                },
+            },
+        ];
+
+        World::from_spheres(spheres).with_background(Background::Solid(Color::default()))
+    }
+
+    /// the same rng state always generates the same world
+    fn new_random<R: Rng>(rng: &mut R) -> Self {
+        let mut spheres = vec![];
+
+        let ground_material = Material::Lambertian {
+            albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+        };
+        spheres.push(Sphere {
+            center: Vec3::from([0, -1000, 0]),
+            radius: 1000.0,
+            material: ground_material,
+        });
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let center = Vec3::from([
+                    a as f64 + 0.9 * rng.gen::<f64>(),
+                    0.2,
+                    b as f64 + 0.9 * rng.gen::<f64>(),
+                ]);
+
+                if (center - Vec3::from([4.0, 0.2, 0.0])).length() > 0.9 {
+                    let choose_mat = rng.gen::<f64>();
+                    if choose_mat < 0.8 {
+                        // diffuse
+                        let albedo = Color::random(rng) * Color::random(rng);
+                        let material = Material::Lambertian {
+                            albedo: Texture::Solid(albedo),
+                        };
+                        spheres.push(Sphere {
+                            center,
+                            radius: 0.2,
+                            material,
+                        });
+                    } else if choose_mat < 0.95 {
+                        // metal
+                        let albedo = Color::random_range(rng, 0.5, 1.0);
+                        let fuzz = rng.sample(Uniform::new(0.0, 0.5));
+                        let material = Material::Metal { albedo, fuzz };
+                        spheres.push(Sphere {
+                            center,
+                            radius: 0.2,
+                            material,
+                        });
+                    } else {
+                        // glass
+                        spheres.push(Sphere {
+                            center,
+                            radius: 0.2,
+                            material: Material::Dielectric { ir: 1.5 },
+                        })
+                    }
+                }
+            }
+        }
+
+        spheres.push(Sphere {
+            center: Vec3::from([0, 1, 0]),
+            radius: 1.0,
+            material: Material::Dielectric { ir: 1.5 },
+        });
+
+        spheres.push(Sphere {
+            center: Vec3::from([-4, 1, 0]),
+            radius: 1.0,
+            material: Material::Lambertian {
+                albedo: Texture::Solid(Color::from([0.5, 0.2, 0.1])),
+            },
+        });
+
+        spheres.push(Sphere {
+            center: Vec3::from([4, 1, 0]),
+            radius: 1.0,
+            material: Material::Metal {
+                albedo: Color::from([0.7, 0.6, 0.5]),
+                fuzz: 0.0,
+            },
+        });
+
+        World::from_spheres(spheres)
+    }
+}
+
+/// either one of the builtin scenes ("random", "random-sky", "lights" or
+/// "cornell") or the path to a scene file. The seed is used to generate the
+/// random scenes.
+pub fn load_scene(name: &str, seed: u64) -> Result<Scene, Box<dyn std::error::Error>> {
+    let (world, camera, aspect_ratio) = match name {
+        "random" => (
+            World::new_random(&mut StdRng::seed_from_u64(seed)),
+            CameraSettings::default(),
+            None,
+        ),
+        "random-sky" => {
+            let sky = Sky::new(35.0, 150.0, 3.0, 1.0);
+            let world = World::new_random(&mut StdRng::seed_from_u64(seed))
+                .with_background(Background::Sky(Arc::new(sky)));
+            (world, CameraSettings::default(), None)
+        }
+        "lights" => (World::new_lights(), CameraSettings::default(), None),
+        // the file is embedded, to be available from anywhere
+        "cornell" => {
+            return Ok(scene::parse(
+                include_str!("../scenes/cornell_box.toml"),
+                Path::new("."),
+            )?)
+        }
+        path => return Ok(scene::load(Path::new(path))?),
+    };
+    Ok(Scene {
+        world,
+        camera,
+        image_width: None,
+        aspect_ratio,
+        warnings: vec![],
+    })
+}
+
+/// the passes start with a single sample per pixel so that the image
+/// appears quickly, and then take more and more samples up to this
+const MAX_SAMPLES_PER_PASS: usize = 16;
+
+pub struct BackgroundWorker {
+    /// samples per pixel of the finished image
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub sampler: SamplerKind,
+    pub seed: u64,
+}
+
+impl BackgroundWorker {
+    /// given a world and a camera, initiate a background computation
+    /// using multiple threads to compute the image.
+    /// It returns a channel with the tiles of the image, each one with a few
+    /// samples for all of its pixels.
+    /// If that computation is no longer relevant (camera or world changed for exampe)
+    /// the receiver should be dropped and the threads will stop shortly after.
+    /// Otherwise, the channel is closed once the image is finished.
+    /// The samples already in `img`, loaded from a checkpoint for example,
+    /// aren't computed again: each tile resumes from its own samples, since
+    /// a checkpoint can be saved in the middle of a pass.
+    pub fn start(
+        &self,
+        world: Arc<World>,
+        camera: &Camera,
+        img: &ImageBuffer,
+    ) -> mpsc::Receiver<Tile> {
+        let (sender, rx) = mpsc::channel();
+
+        let mut tiles = Tile::grid(camera.image_width, camera.image_height)
+            .into_iter()
+            .map(|tile| {
+                let done = img.tile_samples(&tile);
+                (tile, done)
+            })
+            .collect::<Vec<_>>();
+        let samples_per_pixel = self.samples_per_pixel;
+        let max_depth = self.max_depth;
+        let (sampler, seed) = (self.sampler, self.seed);
+        let camera = Arc::new(camera.clone());
+        thread::spawn(move || {
+            // shuffling the tiles make the image appears in a more uniform manner
+            // which I prefer
+            tiles.shuffle(&mut StdRng::seed_from_u64(seed));
+
+            // the passes are the same as for a render from scratch, so that
+            // the samples are added up in the same order
+            for samples in passes(samples_per_pixel) {
+                let sender = sender.clone();
+                let res = tiles
+                    .par_iter()
+                    .try_for_each_with(sender, |sender, (tile, done)| {
+                        let samples = samples.start.max(*done)..samples.end;
+                        if samples.is_empty() {
+                            return Ok(());
+                        }
+                        let mut sampler = sampler.create(samples_per_pixel, seed);
+                        let tile = render_tile(
+                            &world,
+                            &camera,
+                            max_depth,
+                            tile,
+                            samples,
+                            sampler.as_mut(),
+                        );
+                        sender.send(tile)
+                    });
+
+                // ignore the error since the only error we can get is because
+                // the channel to send the result has been closed. In this case
+                // this thread should just stop and die quietly, what it is
+                // computing is no longer relevant (typically, window got resized)
+                if res.is_err() {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// the indices of the samples computed by each pass over the image, until
+/// there are `samples_per_pixel`
+fn passes(samples_per_pixel: usize) -> impl Iterator<Item = Range<usize>> {
+    let mut pass_size = 1;
+    let mut done = 0;
+    std::iter::from_fn(move || {
+        if done >= samples_per_pixel {
+            return None;
+        }
+        let samples = done..(done + pass_size).min(samples_per_pixel);
+        done = samples.end;
+        pass_size = (pass_size * 2).min(MAX_SAMPLES_PER_PASS);
+        Some(samples)
+    })
+}
+
+/// render an image of `scene`, of the size given by [`Scene::image_size`].
+/// The camera is the one of the settings, not the one of the scene: the
+/// scene's camera is only where the settings start from, before being moved
+/// around in the viewer for example. This gives the same image as a
+/// [`BackgroundWorker`] with the same settings.
+pub fn render(scene: &Scene, settings: &RenderSettings) -> ImageBuffer {
+    let (width, height) = scene.image_size();
+    let camera = settings.camera.to_camera(width, height);
+    let tiles = Tile::grid(width, height);
+    let mut img = ImageBuffer::new(width, height);
+    for samples in passes(settings.samples_per_pixel) {
+        let rendered = tiles
+            .par_iter()
+            .map(|tile| {
+                let mut sampler = settings
+                    .sampler
+                    .create(settings.samples_per_pixel, settings.seed);
+                render_tile(
+                    &scene.world,
+                    &camera,
+                    settings.max_depth,
+                    tile,
+                    samples.clone(),
+                    sampler.as_mut(),
+                )
+            })
+            .collect::<Vec<_>>();
+        for tile in &rendered {
+            img.add_tile(tile);
+        }
+    }
+    img
+}
+
+/// add the samples with the indices in `samples` to each pixel of an
+/// (empty) tile
+fn render_tile(
+    world: &World,
+    camera: &Camera,
+    max_depth: usize,
+    tile: &Tile,
+    samples: Range<usize>,
+    sampler: &mut dyn Sampler,
+) -> Tile {
+    let mut tile = tile.clone();
+    for row in 0..tile.height {
+        // the rows of the image go down, but the camera's v goes up
+        let j = camera.image_height - 1 - (tile.y + row);
+        for col in 0..tile.width {
+            let i = tile.x + col;
+            let mut color = Color::default();
+            for index in samples.clone() {
+                sampler.start_sample(i, j, index);
+                let (du, dv) = sampler.next_2d();
+                let u = (i as f64 + du) / ((camera.image_width - 1) as f64);
+                let v = (j as f64 + dv) / ((camera.image_height - 1) as f64);
+                let ray = camera.get_ray(u, v, sampler);
+                color += ray_color(world, max_depth, ray, sampler);
+            }
+            tile.pixels[row * tile.width + col] = color;
+        }
+    }
+    tile.samples = samples.len();
+    tile
+}
+
+/// follow a path bouncing around the world, gathering the light it finds.
+/// Paths are stopped randomly once they carry little light (russian
+/// roulette), which keeps the result unbiased, `max_depth` is only there to
+/// stop the rare paths which are never absorbed.
+fn ray_color(world: &World, max_depth: usize, mut ray: Ray, sampler: &mut dyn Sampler) -> Color {
+    let mut radiance = Color::default();
+    // how much of the light found at the current bounce reaches the camera
+    let mut throughput = Color::from([1, 1, 1]);
+    // density with which the previous bounce picked `ray`, when the material
+    // there was also lit by sampling the lights. In that case the light found
+    // by that ray has already been partly accounted for.
+    let mut bsdf_pdf = None;
+
+    for depth in 0..max_depth {
+        let Some(hit) = world.hit(&ray, 0.0001, f64::INFINITY) else {
+            let background = world.background.color(&ray);
+            radiance += throughput * background * bsdf_weight(world, &ray, bsdf_pdf, &background);
+            break;
+        };
+
+        let mut emitted = hit.mat.emitted(&hit);
+        emitted = emitted * bsdf_weight(world, &ray, bsdf_pdf, &emitted);
+        let wo = -ray.dir.unit();
+        radiance += throughput * (emitted + sample_lights(world, &hit, &wo, ray.time, sampler));
+
+        let Some(sample) = hit.mat.sample(&hit, &wo, sampler) else {
+            break;
+        };
+        throughput *= sample.weight(&hit.normal);
+        bsdf_pdf = (!sample.specular).then_some(sample.pdf);
+        ray = Ray {
+            orig: hit.p,
+            dir: sample.wi,
+            time: ray.time,
+        };
+
+        if depth + 1 >= RUSSIAN_ROULETTE_DEPTH {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if sampler.next_1d() >= survival {
+                break;
+            }
+            // the surviving paths make up for the ones stopped
+            throughput = throughput / survival;
+        }
+    }
+    radiance
+}
+
+/// weight of the light found by `ray`, when it was picked with a density
+/// `bsdf_pdf` by a material which has also sampled the lights
+fn bsdf_weight(world: &World, ray: &Ray, bsdf_pdf: Option<f64>, light: &Color) -> f64 {
+    match bsdf_pdf {
+        Some(bsdf_pdf) if !light.is_near_zero() => {
+            let light_pdf = world.lights.pdf(&ray.orig, &ray.dir);
+            power_heuristic(bsdf_pdf, light_pdf)
+        }
+        _ => 1.0,
+    }
+}
+
+/// next event estimation: the light arriving at the hit point from a
+/// direction picked towards the lights, weighted against the chance of
+/// finding the same light by scattering (multiple importance sampling).
+/// `time` is the time of the path, at which the shadow ray is cast.
+fn sample_lights(
+    world: &World,
+    hit: &HitRecord,
+    wo: &Vec3,
+    time: f64,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    if hit.mat.is_delta() {
+        return Color::default();
+    }
+    let Some(wi) = world.lights.sample(&hit.p, sampler).map(|dir| dir.unit()) else {
+        return Color::default();
+    };
+    let bsdf_cos = hit.mat.eval(hit, &wi, wo) * hit.normal.dot(&wi).abs();
+    let light_pdf = world.lights.pdf(&hit.p, &wi);
+    if light_pdf == 0.0 || bsdf_cos.is_near_zero() {
+        return Color::default();
+    }
+    let bsdf_pdf = hit.mat.pdf(hit, &wi, wo);
+
+    let shadow_ray = Ray {
+        orig: hit.p,
+        dir: wi,
+        time,
+    };
+    // whatever is in the way of the light casts a shadow
+    let incoming = match world.hit(&shadow_ray, 0.0001, f64::INFINITY) {
+        Some(light_hit) => light_hit.mat.emitted(&light_hit),
+        None => world.background.color(&shadow_ray),
+    };
+    incoming * bsdf_cos * power_heuristic(light_pdf, bsdf_pdf) / light_pdf
+}
+
+/// weight of a sample taken with a density `pdf`, when another technique
+/// could have taken it with a density `other_pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+impl Hittable for World {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        self.objects.hit(ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.objects.lights()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the same image, bit for bit, whatever the number of threads
+    #[test]
+    fn render_is_deterministic() {
+        // with volumes, to also check the way they are sampled
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/cornell_smoke.toml");
+        let mut scene = load_scene(path.to_str().unwrap(), 0).unwrap();
+        scene.image_width = Some(40);
+        scene.aspect_ratio = Some(4.0 / 3.0);
+        for sampler in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let settings = RenderSettings {
+                seed: 17,
+                samples_per_pixel: 6,
+                max_depth: 10,
+                sampler,
+                camera: scene.camera.clone(),
+            };
+            let render_with = |threads: usize| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap()
+                    .install(|| render(&scene, &settings))
+            };
+            let single = render_with(1);
+            let multi = render_with(8);
+            assert_eq!(single.pixels(), multi.pixels(), "{sampler} sampler");
+            // and the image isn't trivially black
+            assert!(single.pixels().iter().any(|(c, _)| c.length() > 0.0));
+        }
+    }
+
+    /// stopping a render in the middle of a pass, as when a checkpoint is
+    /// saved, and resuming it gives the image of an uninterrupted render
+    #[test]
+    fn resumed_render_is_uninterrupted() {
+        let mut scene = load_scene("cornell", 0).unwrap();
+        scene.image_width = Some(100);
+        scene.aspect_ratio = Some(100.0 / 70.0);
+        let settings = RenderSettings {
+            seed: 3,
+            samples_per_pixel: 8,
+            max_depth: 10,
+            sampler: SamplerKind::Sobol,
+            camera: scene.camera.clone(),
+        };
+        let expected = render(&scene, &settings);
+        let (width, height) = scene.image_size();
+        let camera = settings.camera.to_camera(width, height);
+        let tile_count = Tile::grid(width, height).len();
+        let worker = BackgroundWorker {
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            sampler: settings.sampler,
+            seed: settings.seed,
+        };
+        let world = Arc::new(scene.world);
+
+        // the first two passes, and a few tiles of the third one
+        let mut img = ImageBuffer::new(width, height);
+        let tiles = worker.start(Arc::clone(&world), &camera, &img);
+        for tile in tiles.iter().take(2 * tile_count + 5) {
+            img.add_tile(&tile);
+        }
+        drop(tiles);
+        assert!(img.min_samples() < 7);
+        assert!(img.pixels().iter().any(|(_, n)| *n == 7));
+
+        for tile in worker.start(Arc::clone(&world), &camera, &img) {
+            img.add_tile(&tile);
+        }
+        assert_eq!(img.pixels(), expected.pixels());
+    }
+}
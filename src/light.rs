@@ -0,0 +1,369 @@
+//! Lights which can be sampled explicitly, so that small light sources can
+//! be found without waiting for rays to stumble onto them.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    environment::EnvironmentMap,
+    ray::Ray,
+    sampler::{self, Sampler},
+    transform::Transform,
+    vec3::{Point3, Vec3},
+};
+
+/// the shape of an emissive object. What it emits is found by tracing a ray
+/// towards it, so that anything in the way casts a shadow.
+#[derive(Debug, Clone)]
+pub enum Light {
+    Sphere {
+        center: Point3,
+        radius: f64,
+    },
+    Triangle {
+        vertices: [Point3; 3],
+    },
+    /// parallelogram with a corner at `q` and its sides along `u` and `v`
+    Quad {
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+    },
+    /// surrounds the whole scene, infinitely far away
+    Environment(Arc<EnvironmentMap>),
+    /// disk infinitely far away, seen under the same angle from everywhere
+    Sun {
+        /// unit vector towards the center of the disk
+        direction: Vec3,
+        /// in radians
+        angular_radius: f64,
+    },
+}
+
+/// two vectors completing `w` (unit) into an orthonormal basis
+fn orthonormal_basis(w: &Vec3) -> (Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 {
+        Vec3::from([0, 1, 0])
+    } else {
+        Vec3::from([1, 0, 0])
+    };
+    let v = w.cross(&a).unit();
+    let u = w.cross(&v);
+    (u, v)
+}
+
+/// uniform direction in the cone around the unit vector `axis`, whose
+/// half-angle has a cosine of 1 - `one_minus_cos`
+fn sample_cone(axis: &Vec3, one_minus_cos: f64, (x, y): (f64, f64)) -> Vec3 {
+    let z = 1.0 - x * one_minus_cos;
+    let phi = 2.0 * PI * y;
+    let sin = (1.0 - z * z).sqrt();
+    let (u, v) = orthonormal_basis(axis);
+    phi.cos() * sin * u + phi.sin() * sin * v + z * axis
+}
+
+/// density with which `sample_cone` returns `dir`
+fn cone_pdf(axis: &Vec3, one_minus_cos: f64, dir: &Vec3) -> f64 {
+    if dir.unit().dot(axis) < 1.0 - one_minus_cos {
+        return 0.0;
+    }
+    1.0 / (2.0 * PI * one_minus_cos)
+}
+
+/// 1 - cos(angle), without the cancellation for small angles
+fn one_minus_cos(angle: f64) -> f64 {
+    let s = (angle / 2.0).sin();
+    2.0 * s * s
+}
+
+impl Light {
+    /// the same light, moved by `transform`. Spheres stay spheres only when
+    /// the transform preserves angles, otherwise the light isn't sampled
+    /// anymore (it can still be found by chance).
+    pub fn transformed(&self, transform: &Transform) -> Option<Light> {
+        let light = match self {
+            Light::Sphere { center, radius } => Light::Sphere {
+                center: transform.point(center),
+                radius: radius * transform.uniform_scale()?,
+            },
+            Light::Triangle { vertices } => Light::Triangle {
+                vertices: vertices.map(|v| transform.point(&v)),
+            },
+            Light::Quad { q, u, v } => Light::Quad {
+                q: transform.point(q),
+                u: transform.vector(u),
+                v: transform.vector(v),
+            },
+            Light::Environment(_) | Light::Sun { .. } => self.clone(),
+        };
+        Some(light)
+    }
+
+    /// where the directions sampled towards the light can go, `None` for the
+    /// lights infinitely far away
+    fn bounding_box(&self) -> Option<Aabb> {
+        // like for the hittables, avoid boxes without thickness
+        let pad = Vec3::from([1e-6, 1e-6, 1e-6]);
+        match self {
+            Light::Sphere { center, radius } => {
+                let r = Vec3::from([*radius, *radius, *radius]);
+                Some(Aabb::new(center - r, center + r))
+            }
+            Light::Triangle {
+                vertices: [v0, v1, v2],
+            } => Some(Aabb::new(
+                v0.min(v1).min(v2) - pad,
+                v0.max(v1).max(v2) + pad,
+            )),
+            Light::Quad { q, u, v } => {
+                let corners = [q + u, q + v, q + u + v];
+                let bbox = corners.iter().fold(Aabb::new(*q, *q), |acc, c| {
+                    acc.surrounding(&Aabb::new(*c, *c))
+                });
+                Some(Aabb::new(bbox.min - pad, bbox.max + pad))
+            }
+            Light::Environment(_) | Light::Sun { .. } => None,
+        }
+    }
+
+    /// direction from `origin` towards a point of the light, for a point `u`
+    /// of the unit square
+    fn sample(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        match self {
+            Light::Sphere { center, radius } => {
+                let to_center = center - origin;
+                let dist2 = to_center.length_squared();
+                if dist2 <= radius * radius {
+                    // inside the sphere, every direction hits it
+                    return sampler::uniform_sphere(u);
+                }
+                // uniform direction in the cone subtended by the sphere
+                let r2 = radius * radius;
+                let cos_max = (1.0 - r2 / dist2).sqrt();
+                // 1 - cos_max, without the cancellation for far away spheres
+                let one_minus_cos = r2 / dist2 / (1.0 + cos_max);
+                sample_cone(&to_center.unit(), one_minus_cos, u)
+            }
+            Light::Triangle {
+                vertices: [v0, v1, v2],
+            } => {
+                // uniform point on the triangle
+                let s = u.0.sqrt();
+                let b0 = 1.0 - s;
+                let b1 = u.1 * s;
+                let q = b0 * v0 + b1 * v1 + (1.0 - b0 - b1) * v2;
+                q - origin
+            }
+            Light::Quad {
+                q,
+                u: side_u,
+                v: side_v,
+            } => {
+                let p = q + u.0 * side_u + u.1 * side_v;
+                p - origin
+            }
+            Light::Environment(map) => map.sample(u),
+            Light::Sun {
+                direction,
+                angular_radius,
+            } => sample_cone(direction, one_minus_cos(*angular_radius), u),
+        }
+    }
+
+    /// density (with respect to solid angle) with which `sample` returns
+    /// `dir`, 0 if that direction misses the light
+    fn pdf(&self, origin: &Point3, dir: &Vec3) -> f64 {
+        match self {
+            Light::Sphere { center, radius } => {
+                let to_center = center - origin;
+                let dist2 = to_center.length_squared();
+                let r2 = radius * radius;
+                if dist2 <= r2 {
+                    return 1.0 / (4.0 * PI);
+                }
+                let cos_max = (1.0 - r2 / dist2).sqrt();
+                let one_minus_cos = r2 / dist2 / (1.0 + cos_max);
+                cone_pdf(&to_center.unit(), one_minus_cos, dir)
+            }
+            Light::Triangle {
+                vertices: [v0, v1, v2],
+            } => {
+                let n = (v1 - v0).cross(&(v2 - v0));
+                let denom = n.dot(dir);
+                if denom == 0.0 {
+                    return 0.0;
+                }
+                let t = n.dot(&(v0 - origin)) / denom;
+                if t <= 0.0 {
+                    return 0.0;
+                }
+                let q = origin + t * dir;
+                let inside = [(v0, v1), (v1, v2), (v2, v0)]
+                    .iter()
+                    .all(|(a, b)| (*b - *a).cross(&(q - *a)).dot(&n) >= 0.0);
+                if !inside {
+                    return 0.0;
+                }
+                // convert the uniform density on the area to a solid angle one
+                let area = 0.5 * n.length();
+                let dist2 = (t * dir).length_squared();
+                let cos = denom.abs() / (n.length() * dir.length());
+                dist2 / (cos * area)
+            }
+            Light::Quad { q, u, v } => {
+                let n = u.cross(v);
+                let denom = n.dot(dir);
+                if denom == 0.0 {
+                    return 0.0;
+                }
+                let t = n.dot(&(q - origin)) / denom;
+                if t <= 0.0 {
+                    return 0.0;
+                }
+                let planar = origin + t * dir - q;
+                let w = n / n.length_squared();
+                let alpha = w.dot(&planar.cross(v));
+                let beta = w.dot(&u.cross(&planar));
+                if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+                    return 0.0;
+                }
+                let area = n.length();
+                let dist2 = (t * dir).length_squared();
+                let cos = denom.abs() / (n.length() * dir.length());
+                dist2 / (cos * area)
+            }
+            Light::Environment(map) => map.pdf(dir),
+            Light::Sun {
+                direction,
+                angular_radius,
+            } => cone_pdf(direction, one_minus_cos(*angular_radius), dir),
+        }
+    }
+}
+
+/// all the lights of a world, picked uniformly when sampling
+#[derive(Debug)]
+pub struct LightList {
+    lights: Vec<Light>,
+    /// indices of the lights with a bounding box, to only look at the ones
+    /// in a given direction
+    bounded: Bvh<usize>,
+    /// indices of the other lights, which can be in any direction
+    unbounded: Vec<usize>,
+}
+
+impl LightList {
+    pub fn new(lights: Vec<Light>) -> Self {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) =
+            (0..lights.len()).partition(|&idx| lights[idx].bounding_box().is_some());
+        let bounded = Bvh::with_bounds(bounded, |&idx| {
+            lights[idx].bounding_box().unwrap_or_else(Aabb::empty)
+        });
+        Self {
+            lights,
+            bounded,
+            unbounded,
+        }
+    }
+
+    /// random direction from `origin` towards one of the lights, if any
+    pub fn sample(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let n = self.lights.len();
+        let idx = ((sampler.next_1d() * n as f64) as usize).min(n - 1);
+        Some(self.lights[idx].sample(origin, sampler.next_2d()))
+    }
+
+    /// density with which `sample` returns `dir`. Lights can overlap, so this
+    /// sums the densities of all the lights in that direction, found with a
+    /// BVH: the cost grows with the log of the number of lights, plus the
+    /// number of lights actually along the ray.
+    pub fn pdf(&self, origin: &Point3, dir: &Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let mut sum = self
+            .unbounded
+            .iter()
+            .map(|&idx| self.lights[idx].pdf(origin, dir))
+            .sum::<f64>();
+        let ray = Ray {
+            orig: *origin,
+            dir: *dir,
+            time: 0.0,
+        };
+        self.bounded
+            .for_each_candidate(&ray, 0.0, f64::INFINITY, |&idx| {
+                sum += self.lights[idx].pdf(origin, dir)
+            });
+        sum / self.lights.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::sampler::Independent;
+
+    #[test]
+    fn pdf_sums_the_lights_in_the_direction() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let point = |rng: &mut StdRng| Vec3::random_range(rng, -5.0, 5.0);
+        let mut lights = vec![Light::Sun {
+            direction: Vec3::from([0, 1, 0]),
+            angular_radius: 0.3,
+        }];
+        for _ in 0..50 {
+            lights.push(Light::Sphere {
+                center: point(&mut rng),
+                radius: rng.gen_range(0.1..1.0),
+            });
+            let q = point(&mut rng);
+            lights.push(Light::Triangle {
+                vertices: [q, q + point(&mut rng) * 0.3, q + point(&mut rng) * 0.3],
+            });
+            lights.push(Light::Quad {
+                q: point(&mut rng),
+                u: point(&mut rng) * 0.3,
+                v: point(&mut rng) * 0.3,
+            });
+        }
+        // axis aligned, with a flat bounding box
+        lights.push(Light::Quad {
+            q: Vec3::from([-1, 4, -1]),
+            u: Vec3::from([2, 0, 0]),
+            v: Vec3::from([0, 0, 2]),
+        });
+        let list = LightList::new(lights.clone());
+
+        let mut nonzero = 0;
+        for _ in 0..2000 {
+            let origin = point(&mut rng);
+            let dir = match list.sample(&origin, &mut Independent::new(rng.gen())) {
+                // half towards the lights, half anywhere
+                Some(dir) if rng.gen_bool(0.5) => dir,
+                _ => sampler::uniform_sphere((rng.gen(), rng.gen())),
+            };
+            let expected = lights
+                .iter()
+                .map(|light| light.pdf(&origin, &dir))
+                .sum::<f64>()
+                / lights.len() as f64;
+            let pdf = list.pdf(&origin, &dir);
+            assert!(
+                (pdf - expected).abs() <= 1e-9 * expected,
+                "{pdf} != {expected}"
+            );
+            nonzero += (pdf > 0.0) as usize;
+        }
+        assert!(
+            nonzero > 500,
+            "only {nonzero} directions towards the lights"
+        );
+    }
+}
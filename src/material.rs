@@ -1,9 +1,15 @@
+use std::f64::consts::PI;
+
 use crate::{
+    aabb::Aabb,
+    light::Light,
     ray::{Face, HitRecord, Hittable, Ray},
+    sampler::{self, hash_to_unit, Sampler},
+    texture::Texture,
     vec3::{Color, Point3, Vec3},
 };
 
-// pub(crate) trait Material {
+// pub trait Material {
 //     /// produce a scattered ray (if not completely absorbed)
 //     /// and say by how much it should be attenuated
 //     fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)>;
@@ -18,10 +24,10 @@ use crate::{
 //     }
 // }
 
-#[derive(Debug, Clone)]
-pub(crate) enum Material {
+#[derive(Debug, Clone, PartialEq)]
+pub enum Material {
     Lambertian {
-        albedo: Color,
+        albedo: Texture,
     },
     /// fuzz should be in [0;1]
     Metal {
@@ -32,6 +38,14 @@ pub(crate) enum Material {
         /// index of refraction
         ir: f64,
     },
+    /// emits light and doesn't scatter anything
+    DiffuseLight {
+        emit: Color,
+    },
+    /// phase function of a volume, scattering the same in all directions
+    Isotropic {
+        albedo: Texture,
+    },
 }
 
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
@@ -52,66 +66,220 @@ fn reflectance(cos: f64, ref_idx: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
 }
 
+/// a direction picked by `Material::sample`
+#[derive(Debug)]
+pub struct BsdfSample {
+    /// unit direction of the scattered ray, where the light comes from
+    pub wi: Vec3,
+    /// value of the BSDF for `wi`
+    pub value: Color,
+    /// density with which `wi` was picked, with respect to solid angle.
+    /// Always positive for the samples returned by `Material::sample`.
+    pub pdf: f64,
+    /// picked from a delta distribution (perfect mirror or glass), which
+    /// `eval` and `pdf` don't account for. `value` is then the fraction of
+    /// the light going in that direction, and `pdf` the probability of
+    /// picking it.
+    pub specular: bool,
+}
+
+impl BsdfSample {
+    /// by how much the light coming from `wi` is attenuated, given the
+    /// shading normal
+    pub fn weight(&self, normal: &Vec3) -> Color {
+        if self.pdf <= 0.0 {
+            return Color::default();
+        }
+        if self.specular {
+            self.value / self.pdf
+        } else {
+            self.value * self.wi.dot(normal).abs() / self.pdf
+        }
+    }
+}
+
+/// the directions of points uniformly distributed in a ball of radius `fuzz`
+/// around the tip of the unit vector `reflected`. The density for `wi` is the
+/// integral of t² along the part of the ray t*wi within the ball.
+fn fuzzy_reflection_pdf(reflected: &Vec3, fuzz: f64, wi: &Vec3) -> f64 {
+    let b = wi.dot(reflected);
+    let discriminant = b * b - 1.0 + fuzz * fuzz;
+    if discriminant <= 0.0 {
+        return 0.0;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((b - sqrtd).max(0.0), b + sqrtd);
+    if t1 <= 0.0 {
+        return 0.0;
+    }
+    let volume = 4.0 / 3.0 * PI * fuzz * fuzz * fuzz;
+    (t1 - t0) * (t1 * t1 + t1 * t0 + t0 * t0) / (3.0 * volume)
+}
+
 impl Material {
-    pub(crate) fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
-        match self {
+    /// pick a direction for the scattered ray, given `wo` the unit direction
+    /// towards where the incoming ray comes from. None if the light is
+    /// absorbed, or if the direction picked has a pdf of 0.
+    pub fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BsdfSample> {
+        let sample = match self {
             Material::Lambertian { albedo } => {
-                let mut scatter_direction = hit.normal + Vec3::random_unit_vector();
+                // cosine weighted
+                let mut scatter_direction = hit.normal + sampler::uniform_sphere(sampler.next_2d());
                 if scatter_direction.is_near_zero() {
                     scatter_direction = hit.normal;
                 }
-                let scattered = Ray {
-                    orig: hit.p,
-                    dir: scatter_direction,
-                };
-                let attenuation = *albedo;
-                Some((scattered, attenuation))
+                let wi = scatter_direction.unit();
+                Some(BsdfSample {
+                    wi,
+                    value: albedo.value(hit.u, hit.v, &hit.p) / PI,
+                    pdf: hit.normal.dot(&wi).max(0.0) / PI,
+                    specular: false,
+                })
             }
             Material::Metal { albedo, fuzz } => {
-                let v = ray_in.dir.unit();
-                let reflected = reflect(&v, &hit.normal);
-                let scattered = Ray {
-                    orig: hit.p,
-                    dir: reflected + *fuzz * Vec3::random_in_unit_sphere(),
-                };
-                if scattered.dir.dot(&hit.normal) > 0.0 {
-                    let attenuation = *albedo;
-                    Some((scattered, attenuation))
-                } else {
-                    None
+                let reflected = reflect(&-wo, &hit.normal);
+                if *fuzz == 0.0 {
+                    return Some(BsdfSample {
+                        wi: reflected,
+                        value: *albedo,
+                        pdf: 1.0,
+                        specular: true,
+                    });
+                }
+                let ball = sampler::uniform_ball(sampler.next_2d(), sampler.next_1d());
+                let dir = reflected + *fuzz * ball;
+                if dir.dot(&hit.normal) <= 0.0 {
+                    return None;
                 }
+                let wi = dir.unit();
+                Some(BsdfSample {
+                    wi,
+                    value: self.eval(hit, &wi, wo),
+                    pdf: fuzzy_reflection_pdf(&reflected, *fuzz, &wi),
+                    specular: false,
+                })
             }
             Material::Dielectric { ir } => {
-                let attenuation = Color::from([1.0, 1.0, 1.0]);
                 let refraction_ratio = match hit.face {
                     Face::Front => 1.0 / ir,
                     Face::Back => *ir,
                 };
-                let unit_direction = ray_in.dir.unit();
-                let cos_theta = (-unit_direction.dot(&hit.normal)).min(1.0);
+                let unit_direction = -wo;
+                let cos_theta = wo.dot(&hit.normal).min(1.0);
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-                let cannot_refract = refraction_ratio * sin_theta > 1.0;
-                let dir = if cannot_refract
-                    || reflectance(cos_theta, refraction_ratio) > rand::random()
-                {
-                    reflect(&unit_direction, &hit.normal)
+                let reflect_prob = if refraction_ratio * sin_theta > 1.0 {
+                    1.0
                 } else {
-                    refract(&unit_direction, &hit.normal, refraction_ratio)
+                    reflectance(cos_theta, refraction_ratio)
                 };
+                // both directions let all the light through, the fresnel
+                // term is only used to pick one
+                let (wi, prob) = if reflect_prob > sampler.next_1d() {
+                    (reflect(&unit_direction, &hit.normal), reflect_prob)
+                } else {
+                    (
+                        refract(&unit_direction, &hit.normal, refraction_ratio).unit(),
+                        1.0 - reflect_prob,
+                    )
+                };
+                Some(BsdfSample {
+                    wi,
+                    value: prob * Color::from([1, 1, 1]),
+                    pdf: prob,
+                    specular: true,
+                })
+            }
+            Material::DiffuseLight { .. } => None,
+            Material::Isotropic { .. } => {
+                let wi = sampler::uniform_sphere(sampler.next_2d());
+                Some(BsdfSample {
+                    wi,
+                    value: self.eval(hit, &wi, wo),
+                    pdf: 1.0 / (4.0 * PI),
+                    specular: false,
+                })
+            }
+        };
+        // at the edge of the distribution, the weight would be infinite
+        sample.filter(|sample| sample.pdf > 0.0)
+    }
+
+    /// value of the BSDF for light coming from `wi` and leaving towards
+    /// `wo`, both unit vectors. Always black for the specular directions.
+    pub fn eval(&self, hit: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        if let Material::Isotropic { albedo } = self {
+            // phase functions have no cosine term, but the integrator treats
+            // everything as a surface and multiplies by one
+            let cos = hit.normal.dot(wi).abs();
+            if cos == 0.0 {
+                return Color::default();
+            }
+            return albedo.value(hit.u, hit.v, &hit.p) / (4.0 * PI * cos);
+        }
+        let cos = hit.normal.dot(wi);
+        if cos <= 0.0 {
+            return Color::default();
+        }
+        match self {
+            Material::Lambertian { albedo } => albedo.value(hit.u, hit.v, &hit.p) / PI,
+            // whatever makes the sampling perfect, since that's how this
+            // material is defined
+            Material::Metal { albedo, fuzz } if *fuzz > 0.0 => {
+                let reflected = reflect(&-wo, &hit.normal);
+                *albedo * fuzzy_reflection_pdf(&reflected, *fuzz, wi) / cos
+            }
+            _ => Color::default(),
+        }
+    }
 
-                let scattered = Ray { orig: hit.p, dir };
-                Some((scattered, attenuation))
+    /// density with which `sample` picks `wi`, 0 for the specular directions
+    pub fn pdf(&self, hit: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        match self {
+            Material::Lambertian { .. } => hit.normal.dot(wi).max(0.0) / PI,
+            Material::Isotropic { .. } => 1.0 / (4.0 * PI),
+            Material::Metal { fuzz, .. } if *fuzz > 0.0 => {
+                let reflected = reflect(&-wo, &hit.normal);
+                fuzzy_reflection_pdf(&reflected, *fuzz, wi)
             }
+            _ => 0.0,
+        }
+    }
+
+    /// scatters light only in a few discrete directions, or not at all.
+    /// Sampling lights is useless for these.
+    pub fn is_delta(&self) -> bool {
+        match self {
+            Material::Lambertian { .. } | Material::Isotropic { .. } => false,
+            Material::Metal { fuzz, .. } => *fuzz == 0.0,
+            Material::Dielectric { .. } | Material::DiffuseLight { .. } => true,
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::DiffuseLight { .. })
+    }
+
+    /// how much light is emitted by the material at the hit point.
+    /// Black for everything but lights.
+    pub fn emitted(&self, _hit: &HitRecord) -> Color {
+        match self {
+            Material::DiffuseLight { emit } => *emit,
+            _ => Color::default(),
         }
     }
 }
 
 #[derive(Debug)]
-pub(crate) struct Sphere {
-    pub(crate) center: Point3,
-    pub(crate) radius: f64,
-    pub(crate) material: Material,
+pub struct Sphere {
+    pub center: Point3,
+    pub radius: f64,
+    pub material: Material,
 }
 
 // impl std::fmt::Debug for Sphere {
@@ -124,30 +292,186 @@ pub(crate) struct Sphere {
 //     }
 // }
 
+/// (u, v) coordinates of a point on the unit sphere. u goes around the y
+/// axis starting from -x, and v goes from the bottom (-y) to the top (+y)
+fn sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+fn hit_sphere<'a>(
+    center: &Point3,
+    radius: f64,
+    material: &'a Material,
+    ray: &Ray,
+    tmin: f64,
+    tmax: f64,
+) -> Option<HitRecord<'a>> {
+    let oc = ray.orig - center;
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    // find the nearest root that lies in the acceptable range
+    let mut root = (-half_b - sqrtd) / a;
+    if root < tmin || tmax < root {
+        root = (-half_b + sqrtd) / a;
+        if root < tmin || tmax < root {
+            return None;
+        }
+    }
+
+    let p = ray.at(root);
+    let outward_normal = (p - center) / radius;
+    let uv = sphere_uv(&((p - center) / radius.abs()));
+    Some(HitRecord::new(p, outward_normal, root, uv, ray, material))
+}
+
+fn sphere_bounding_box(center: &Point3, radius: f64) -> Aabb {
+    // the radius can be negative for hollow spheres
+    let r = radius.abs();
+    let r = Vec3::from([r, r, r]);
+    Aabb::new(center - r, center + r)
+}
+
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord> {
-        let oc = ray.orig - self.center;
-        let a = ray.dir.length_squared();
-        let half_b = oc.dot(&ray.dir);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        hit_sphere(&self.center, self.radius, &self.material, ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        sphere_bounding_box(&self.center, self.radius)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if !self.material.is_emissive() {
+            return vec![];
+        }
+        vec![Light::Sphere {
+            center: self.center,
+            radius: self.radius.abs(),
+        }]
+    }
+}
+
+/// sphere moving in a straight line, from `center0` at `time0` to `center1`
+/// at `time1`. It stays still before and after.
+#[derive(Debug)]
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f64) -> Point3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        let center = self.center(ray.time);
+        hit_sphere(&center, self.radius, &self.material, ray, tmin, tmax)
+    }
+
+    /// surrounds the whole movement
+    fn bounding_box(&self) -> Aabb {
+        sphere_bounding_box(&self.center0, self.radius)
+            .surrounding(&sphere_bounding_box(&self.center1, self.radius))
+    }
+
+    // a light which moves can't be sampled from a fixed position, so a moving
+    // sphere is never a light, even when it emits
+}
+
+/// volume of constant density, such as fog or smoke, filling a boundary.
+/// The boundary must be convex: a ray entering it must leave it only once.
+#[derive(Debug)]
+pub struct ConstantMedium<T> {
+    boundary: T,
+    neg_inv_density: f64,
+    phase_function: Material,
+}
+
+impl<T: Hittable> ConstantMedium<T> {
+    /// the density is the probability of scattering per unit of length
+    pub fn new(boundary: T, density: f64, albedo: Texture) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Material::Isotropic { albedo },
+        }
+    }
+}
+
+impl<T: Hittable> Hittable for ConstantMedium<T> {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        // where the ray enters and leaves the boundary, even behind its
+        // origin when it starts inside
+        let enter = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let leave = self.boundary.hit(ray, enter.t + 0.0001, f64::INFINITY)?;
+        let t0 = enter.t.max(tmin);
+        let t1 = leave.t.min(tmax);
+        if t0 >= t1 {
             return None;
         }
 
-        let sqrtd = discriminant.sqrt();
-        // find the nearest root that lies in the acceptable range
-        let mut root = (-half_b - sqrtd) / a;
-        if root < tmin || tmax < root {
-            root = (-half_b + sqrtd) / a;
-            if root < tmin || tmax < root {
-                return None;
-            }
+        let length = ray.dir.length();
+        let distance_inside = (t1 - t0) * length;
+        // The free flight distance isn't drawn from the path's sampler: `hit`
+        // is called for the media whose bounding box the ray happens to reach
+        // during the BVH traversal, a number of times depending on the scene
+        // and on the traversal order, which would shift the dimensions used
+        // by everything after it along the path. Hashing the ray instead gives
+        // the same distance whenever the same ray is traced, whatever the
+        // thread and however the tmax shrinks, and a different one for each
+        // medium along it, thanks to where it enters them.
+        let u = hash_to_unit(&[
+            ray.orig.x.to_bits(),
+            ray.orig.y.to_bits(),
+            ray.orig.z.to_bits(),
+            ray.dir.x.to_bits(),
+            ray.dir.y.to_bits(),
+            ray.dir.z.to_bits(),
+            ray.time.to_bits(),
+            enter.t.to_bits(),
+        ]);
+        let hit_distance = self.neg_inv_density * (1.0 - u).ln();
+        if hit_distance > distance_inside {
+            return None;
         }
 
-        let p = ray.at(root);
-        let outward_normal = (p - self.center) / self.radius;
-        Some(HitRecord::new(p, outward_normal, root, ray, &self.material))
+        let t = t0 + hit_distance / length;
+        let p = ray.at(t);
+        // the normal means nothing inside a volume, it's only used by the
+        // phase function to undo the cosine term
+        Some(HitRecord {
+            p,
+            normal: -ray.dir / length,
+            t,
+            u: 0.0,
+            v: 0.0,
+            face: Face::Front,
+            mat: &self.phase_function,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
     }
 }
 
@@ -155,7 +479,7 @@ impl<T> Hittable for Vec<T>
 where
     T: Hittable,
 {
-    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
         let mut closest_so_far = tmax;
         let mut hit = None;
 
@@ -167,4 +491,287 @@ where
         }
         hit
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.iter().fold(Aabb::empty(), |acc, obj| {
+            acc.surrounding(&obj.bounding_box())
+        })
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.iter().flat_map(Hittable::lights).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{quad::BoxShape, sampler::Independent};
+
+    const THETA_BINS: usize = 40;
+    const PHI_BINS: usize = 80;
+    const SAMPLES: usize = 200_000;
+
+    fn hit_for<'a>(material: &'a Material, wo: &Vec3) -> HitRecord<'a> {
+        let ray = Ray {
+            orig: *wo,
+            dir: -wo,
+            time: 0.0,
+        };
+        let normal = Vec3::from([0, 0, 1]);
+        HitRecord::new(Vec3::default(), normal, 1.0, (0.5, 0.5), &ray, material)
+    }
+
+    fn direction(theta: f64, phi: f64) -> Vec3 {
+        Vec3::from([
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ])
+    }
+
+    fn bin_of(wi: &Vec3) -> usize {
+        let theta = wi.z.clamp(-1.0, 1.0).acos();
+        let phi = wi.y.atan2(wi.x).rem_euclid(2.0 * PI);
+        let i = ((theta / PI * THETA_BINS as f64) as usize).min(THETA_BINS - 1);
+        let j = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+        i * PHI_BINS + j
+    }
+
+    /// probability of each (theta, phi) bin according to `Material::pdf`
+    fn integrate_pdf(material: &Material, wo: &Vec3) -> Vec<f64> {
+        let hit = hit_for(material, wo);
+        let sub = 8;
+        let dtheta = PI / (THETA_BINS * sub) as f64;
+        let dphi = 2.0 * PI / (PHI_BINS * sub) as f64;
+        let mut probs = vec![0.0; THETA_BINS * PHI_BINS];
+        for i in 0..THETA_BINS * sub {
+            let theta = (i as f64 + 0.5) * dtheta;
+            for j in 0..PHI_BINS * sub {
+                let phi = (j as f64 + 0.5) * dphi;
+                let wi = direction(theta, phi);
+                let bin = (i / sub) * PHI_BINS + j / sub;
+                probs[bin] += material.pdf(&hit, &wi, wo) * theta.sin() * dtheta * dphi;
+            }
+        }
+        probs
+    }
+
+    /// Pearson's chi-square test between the directions picked by `sample`
+    /// and the ones expected from `pdf`. Absorbed samples go below the
+    /// surface, so all the bins below are merged into a single one.
+    fn chi_square_test(material: &Material, wo: &Vec3) {
+        let wo = wo.unit();
+        let hit = hit_for(material, &wo);
+        let probs = integrate_pdf(material, &wo);
+        let total = probs.iter().sum::<f64>();
+        assert!(
+            (total - 1.0).abs() < 1e-2,
+            "pdf of {material:?} integrates to {total}"
+        );
+
+        let mut sampler = Independent::new(42);
+        let mut observed = vec![0.0; THETA_BINS * PHI_BINS];
+        let mut absorbed = 0.0;
+        for _ in 0..SAMPLES {
+            match material.sample(&hit, &wo, &mut sampler) {
+                Some(sample) => observed[bin_of(&sample.wi)] += 1.0,
+                None => absorbed += 1.0,
+            }
+        }
+
+        let below = THETA_BINS / 2 * PHI_BINS;
+        let mut cells = vec![(
+            observed[below..].iter().sum::<f64>() + absorbed,
+            probs[below..].iter().sum::<f64>() * SAMPLES as f64,
+        )];
+        // bins with too few expected samples are pooled together
+        let mut pooled = (0.0, 0.0);
+        for (obs, prob) in observed[..below].iter().zip(&probs[..below]) {
+            let expected = prob * SAMPLES as f64;
+            if expected < 5.0 {
+                pooled = (pooled.0 + obs, pooled.1 + expected);
+            } else {
+                cells.push((*obs, expected));
+            }
+        }
+        cells.push(pooled);
+        let cells = cells
+            .into_iter()
+            .filter(|(obs, expected)| *obs > 0.0 || *expected > 0.0)
+            .collect::<Vec<_>>();
+
+        let chi2 = cells
+            .iter()
+            .map(|(obs, expected)| (obs - expected) * (obs - expected) / expected.max(1e-12))
+            .sum::<f64>();
+        // Wilson-Hilferty transformation to a standard normal variable
+        let dof = (cells.len() - 1) as f64;
+        let k = 2.0 / (9.0 * dof);
+        let z = ((chi2 / dof).cbrt() - (1.0 - k)) / k.sqrt();
+        assert!(
+            z < 4.0,
+            "sampling of {material:?} (wo = {wo}) doesn't match its pdf: chi2 = {chi2} for {dof} degrees of freedom"
+        );
+    }
+
+    fn glossy_materials() -> Vec<Material> {
+        vec![
+            Material::Lambertian {
+                albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+            },
+            Material::Metal {
+                albedo: Color::from([0.8, 0.6, 0.2]),
+                fuzz: 0.5,
+            },
+            Material::Metal {
+                albedo: Color::from([0.8, 0.6, 0.2]),
+                fuzz: 1.0,
+            },
+            Material::Isotropic {
+                albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+            },
+        ]
+    }
+
+    fn incoming_directions() -> Vec<Vec3> {
+        vec![Vec3::from([0, 0, 1]), Vec3::from([0.8, 0.3, 0.5])]
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        for material in glossy_materials() {
+            for wo in incoming_directions() {
+                chi_square_test(&material, &wo);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_match_eval() {
+        let mut sampler = Independent::new(7);
+        for material in glossy_materials() {
+            for wo in incoming_directions() {
+                let wo = wo.unit();
+                let hit = hit_for(&material, &wo);
+                for _ in 0..1000 {
+                    let Some(sample) = material.sample(&hit, &wo, &mut sampler) else {
+                        continue;
+                    };
+                    assert!(!sample.specular);
+                    assert!(sample.pdf > 0.0);
+                    let value = material.eval(&hit, &sample.wi, &wo);
+                    let pdf = material.pdf(&hit, &sample.wi, &wo);
+                    assert!((value - sample.value).length() <= 1e-9 * value.length());
+                    assert!((pdf - sample.pdf).abs() <= 1e-9 * pdf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn glass_lets_all_the_light_through() {
+        let mut sampler = Independent::new(7);
+        let glass = Material::Dielectric { ir: 1.5 };
+        for wo in incoming_directions() {
+            let wo = wo.unit();
+            let hit = hit_for(&glass, &wo);
+            for _ in 0..1000 {
+                let sample = glass.sample(&hit, &wo, &mut sampler).unwrap();
+                assert!(sample.specular);
+                let weight = sample.weight(&hit.normal);
+                assert!((weight - Color::from([1, 1, 1])).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_pdf_weighs_nothing() {
+        let normal = Vec3::from([0, 0, 1]);
+        for specular in [false, true] {
+            let sample = BsdfSample {
+                wi: Vec3::from([1, 0, 0]),
+                value: Color::from([0.5, 0.5, 0.5]),
+                pdf: 0.0,
+                specular,
+            };
+            assert_eq!(sample.weight(&normal), Color::default());
+        }
+    }
+
+    #[test]
+    fn moving_sphere() {
+        let sphere = MovingSphere {
+            center0: Vec3::from([0, 0, 0]),
+            center1: Vec3::from([4, 1, 0]),
+            time0: 0.0,
+            time1: 1.0,
+            radius: 0.5,
+            material: Material::Lambertian {
+                albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+            },
+        };
+        let bbox = sphere.bounding_box();
+        assert_eq!(bbox.min, Vec3::from([-0.5, -0.5, -0.5]));
+        assert_eq!(bbox.max, Vec3::from([4.5, 1.5, 0.5]));
+
+        // rays along z through either end of the movement
+        let ray = |x: f64, y: f64, time: f64| Ray {
+            orig: Vec3::from([x, y, 5.0]),
+            dir: Vec3::from([0, 0, -1]),
+            time,
+        };
+        for (time, (x, y)) in [(0.0, (0.0, 0.0)), (1.0, (4.0, 1.0)), (0.5, (2.0, 0.5))] {
+            let hit = sphere.hit(&ray(x, y, time), 0.0, f64::INFINITY).unwrap();
+            assert!((hit.t - 4.5).abs() < 1e-12, "at time {time}");
+            assert!((hit.normal - Vec3::from([0, 0, 1])).length() < 1e-12);
+        }
+        assert!(sphere
+            .hit(&ray(0.0, 0.0, 1.0), 0.0, f64::INFINITY)
+            .is_none());
+        assert!(sphere
+            .hit(&ray(4.0, 1.0, 0.0), 0.0, f64::INFINITY)
+            .is_none());
+        // the sphere stays at the ends outside of its movement
+        assert!(sphere
+            .hit(&ray(4.0, 1.0, 2.0), 0.0, f64::INFINITY)
+            .is_some());
+    }
+
+    #[test]
+    fn transmittance_through_a_slab() {
+        let white = Texture::Solid(Color::from([1, 1, 1]));
+        let n = 20_000;
+        for (density, thickness) in [(0.5, 2.0), (2.0, 0.3), (0.1, 5.0)] {
+            let slab = BoxShape::new(
+                Vec3::from([-100.0, -100.0, 0.0]),
+                Vec3::from([100.0, 100.0, thickness]),
+                Arc::new(Material::Dielectric { ir: 1.0 }),
+            );
+            let medium = ConstantMedium::new(slab, density, white.clone());
+            let mut sampler = Independent::new(3);
+            let through = (0..n)
+                .filter(|_| {
+                    let (x, y) = sampler.next_2d();
+                    let ray = Ray {
+                        orig: Vec3::from([x, y, thickness + 1.0]),
+                        dir: Vec3::from([0, 0, -1]),
+                        time: 0.0,
+                    };
+                    medium.hit(&ray, 0.0001, f64::INFINITY).is_none()
+                })
+                .count();
+            let fraction = through as f64 / n as f64;
+            let expected = (-density * thickness).exp();
+            // 4 standard deviations
+            let tolerance = 4.0 * (expected * (1.0 - expected) / n as f64).sqrt();
+            assert!(
+                (fraction - expected).abs() < tolerance,
+                "{fraction} of the rays go through a slab of {thickness} with a density of \
+                 {density}, expected {expected}"
+            );
+        }
+    }
 }
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    light::Light,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    vec3::{Point3, Vec3},
+};
+
+/// a bunch of triangles, with their own acceleration structure
+pub type Mesh = Bvh<Triangle>;
+
+#[derive(Debug)]
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    /// per vertex normals, interpolated across the face for smooth shading.
+    /// If absent, the geometric normal is used.
+    pub normals: Option<[Vec3; 3]>,
+    /// per vertex texture coordinates. If absent, the barycentric coordinates
+    /// are used instead.
+    pub uvs: Option<[(f64, f64); 3]>,
+    /// shared between all the triangles of a mesh using the same material
+    pub material: Arc<Material>,
+}
+
+/// index of the axis with the largest absolute value
+fn max_dimension(v: &Vec3) -> usize {
+    let (x, y, z) = (v.x.abs(), v.y.abs(), v.z.abs());
+    if x > y {
+        if x > z {
+            0
+        } else {
+            2
+        }
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+impl Hittable for Triangle {
+    /// Watertight ray/triangle intersection (Woop, Benthin, Wald 2013).
+    /// The triangle is transformed into a space where the ray starts at the
+    /// origin and goes along +z, so that rays going exactly through an edge
+    /// or a vertex shared by several triangles hit exactly one of them.
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        let kz = max_dimension(&ray.dir);
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        // preserve the winding of the triangle
+        if ray.dir[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        let sx = ray.dir[kx] / ray.dir[kz];
+        let sy = ray.dir[ky] / ray.dir[kz];
+        let sz = 1.0 / ray.dir[kz];
+
+        let a = self.vertices[0] - ray.orig;
+        let b = self.vertices[1] - ray.orig;
+        let c = self.vertices[2] - ray.orig;
+
+        // shear and scale the vertices
+        let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+        let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+        let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+        // scaled barycentric coordinates
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+        // the ray goes through an edge (or a vertex): only keep the hit if the
+        // triangle owns that edge. The coordinates of a shared edge are the
+        // opposite of each other in the two triangles, so that only one of
+        // them does.
+        let sign = det.signum();
+        let owns = |coord: f64, (px, py): (f64, f64), (qx, qy): (f64, f64)| {
+            let (dx, dy) = (sign * (qx - px), sign * (qy - py));
+            coord != 0.0 || dy > 0.0 || (dy == 0.0 && dx < 0.0)
+        };
+        let (a2, b2, c2) = ((ax, ay), (bx, by), (cx, cy));
+        if !(owns(u, b2, c2) && owns(v, c2, a2) && owns(w, a2, b2)) {
+            return None;
+        }
+
+        let t = (u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz]) / det;
+        if t < tmin || tmax < t {
+            return None;
+        }
+
+        let (b0, b1, b2) = (u / det, v / det, w / det);
+        let p = ray.at(t);
+
+        let [v0, v1, v2] = self.vertices;
+        let geometric_normal = (v1 - v0).cross(&(v2 - v0)).unit();
+        let outward_normal = match &self.normals {
+            Some([n0, n1, n2]) => {
+                let n = (b0 * n0 + b1 * n1 + b2 * n2).unit();
+                // keep the shading normal on the same side as the actual
+                // surface, otherwise the face computation is wrong
+                if n.dot(&geometric_normal) < 0.0 {
+                    -n
+                } else {
+                    n
+                }
+            }
+            None => geometric_normal,
+        };
+
+        let uv = match &self.uvs {
+            Some([(u0, v0), (u1, v1), (u2, v2)]) => {
+                (b0 * u0 + b1 * u1 + b2 * u2, b0 * v0 + b1 * v1 + b2 * v2)
+            }
+            None => (b1, b2),
+        };
+
+        Some(HitRecord::new(
+            p,
+            outward_normal,
+            t,
+            uv,
+            ray,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [v0, v1, v2] = self.vertices;
+        // pad the box a little bit to avoid a zero thickness for triangles
+        // aligned with an axis
+        let pad = Vec3::from([1e-6, 1e-6, 1e-6]);
+        Aabb::new(v0.min(&v1).min(&v2) - pad, v0.max(&v1).max(&v2) + pad)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if !self.material.is_emissive() {
+            return vec![];
+        }
+        vec![Light::Triangle {
+            vertices: self.vertices,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::texture::Texture;
+
+    fn triangle(vertices: [Point3; 3]) -> Triangle {
+        Triangle {
+            vertices,
+            normals: None,
+            uvs: None,
+            material: Arc::new(Material::Lambertian {
+                albedo: Texture::Solid(Vec3::from([0.5, 0.5, 0.5])),
+            }),
+        }
+    }
+
+    /// number of triangles hit by rays from random origins above the z = 0
+    /// plane, going through `target`
+    fn hit_counts(triangles: &[Triangle], target: Point3) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let straight = Ray {
+            orig: target + Vec3::from([0, 0, 1]),
+            dir: Vec3::from([0, 0, -1]),
+            time: 0.0,
+        };
+        let random = (0..200).map(|_| {
+            let orig = Vec3::from([
+                rng.gen_range(-3.0..3.0),
+                rng.gen_range(-3.0..3.0),
+                rng.gen_range(0.5..3.0),
+            ]);
+            Ray {
+                orig,
+                dir: target - orig,
+                time: 0.0,
+            }
+        });
+        std::iter::once(straight)
+            .chain(random)
+            .map(|ray| {
+                triangles
+                    .iter()
+                    .filter(|tri| tri.hit(&ray, 0.0, f64::INFINITY).is_some())
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hit_inside() {
+        let tri = triangle([
+            Vec3::from([0, 0, 0]),
+            Vec3::from([1, 0, 0]),
+            Vec3::from([0, 1, 0]),
+        ]);
+        let ray = Ray {
+            orig: Vec3::from([0.25, 0.25, 2.0]),
+            dir: Vec3::from([0, 0, -1]),
+            time: 0.0,
+        };
+        let hit = tri.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert!(tri.hit(&ray, 0.0, 1.0).is_none());
+
+        let miss = Ray {
+            orig: Vec3::from([0.75, 0.75, 2.0]),
+            ..ray
+        };
+        assert!(tri.hit(&miss, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn shared_edge_is_hit_once() {
+        // a square split along its diagonal
+        let [p0, p1, p2, p3] = [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]].map(Vec3::from);
+        let triangles = [triangle([p0, p1, p2]), triangle([p0, p2, p3])];
+        for k in 1..10 {
+            let target = Vec3::from([0.1, 0.1, 0.0]) * k as f64;
+            for count in hit_counts(&triangles, target) {
+                assert_eq!(count, 1, "through {target:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn shared_vertex_is_hit_once() {
+        // a hexagon made of a fan of triangles around its center
+        let center = Vec3::from([0.5, 0.5, 0.0]);
+        let corner = |i: usize| {
+            let angle = i as f64 * std::f64::consts::PI / 3.0 + 0.1;
+            center + Vec3::from([angle.cos(), angle.sin(), 0.0])
+        };
+        let triangles = (0..6)
+            .map(|i| triangle([center, corner(i), corner(i + 1)]))
+            .collect::<Vec<_>>();
+        for count in hit_counts(&triangles, center) {
+            assert_eq!(count, 1);
+        }
+    }
+}
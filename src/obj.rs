@@ -0,0 +1,427 @@
+//! Minimal Wavefront OBJ loader. Only the geometry (`v`, `vt`, `vn`, `f`) and
+//! the material assignments (`mtllib`, `usemtl`) are read, everything else is
+//! ignored. Polygons are triangulated as fans.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    material::Material,
+    mesh::{Mesh, Triangle},
+    texture::{ImageTexture, Texture},
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Texture(PathBuf, image::ImageError),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        msg: String,
+    },
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "cannot read {}: {err}", path.display()),
+            ObjError::Texture(path, err) => {
+                write!(f, "cannot load texture {}: {err}", path.display())
+            }
+            ObjError::Parse { path, line, msg } => {
+                write!(f, "{}:{line}: {msg}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// load the obj file at the given path. Faces without material, or using
+/// a material which cannot be found, use `default_material`. Along with the
+/// mesh come warnings about what was ignored in the file.
+pub fn load(path: &Path, default_material: &Material) -> Result<(Mesh, Vec<String>), ObjError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| ObjError::Io(path.to_path_buf(), err))?;
+    let (triangles, warnings) = parse(&content, path, default_material)?;
+    Ok((Mesh::new(triangles), warnings))
+}
+
+/// triangles of the obj file `content`, read from `path`, and warnings
+fn parse(
+    content: &str,
+    path: &Path,
+    default_material: &Material,
+) -> Result<(Vec<Triangle>, Vec<String>), ObjError> {
+    let parse_err = |line: usize, msg: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        msg,
+    };
+
+    let default_material = Arc::new(default_material.clone());
+    let mut library: HashMap<String, Arc<Material>> = HashMap::new();
+    let mut current_material = Arc::clone(&default_material);
+
+    let mut positions: Vec<Point3> = vec![];
+    let mut texcoords: Vec<(f64, f64)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut triangles = vec![];
+    let mut warnings = vec![];
+
+    for (idx, line) in content.lines().enumerate() {
+        let lineno = idx + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args = tokens.collect::<Vec<_>>();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&args).map_err(|msg| parse_err(lineno, msg))?),
+            "vn" => normals.push(parse_vec3(&args).map_err(|msg| parse_err(lineno, msg))?),
+            "vt" => texcoords.push(parse_uv(&args).map_err(|msg| parse_err(lineno, msg))?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_err(
+                        lineno,
+                        format!("a face needs at least 3 vertices, got {}", args.len()),
+                    ));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| parse_corner(arg, positions.len(), texcoords.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|msg| parse_err(lineno, msg))?;
+
+                for i in 1..corners.len() - 1 {
+                    let [c0, c1, c2] = [corners[0], corners[i], corners[i + 1]];
+                    let vertices = [
+                        positions[c0.position],
+                        positions[c1.position],
+                        positions[c2.position],
+                    ];
+                    let uvs = match (c0.texcoord, c1.texcoord, c2.texcoord) {
+                        (Some(t0), Some(t1), Some(t2)) => {
+                            Some([texcoords[t0], texcoords[t1], texcoords[t2]])
+                        }
+                        _ => None,
+                    };
+                    let normals = match (c0.normal, c1.normal, c2.normal) {
+                        (Some(n0), Some(n1), Some(n2)) => {
+                            Some([normals[n0], normals[n1], normals[n2]])
+                        }
+                        _ => None,
+                    };
+                    triangles.push(Triangle {
+                        vertices,
+                        normals,
+                        uvs,
+                        material: Arc::clone(&current_material),
+                    });
+                }
+            }
+            "mtllib" => {
+                for name in args {
+                    let mtl_path = path.parent().unwrap_or(Path::new(".")).join(name);
+                    library.extend(load_mtl(&mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_material = match library.get(&name) {
+                    Some(mat) => Arc::clone(mat),
+                    None => {
+                        warnings.push(format!(
+                            "{}:{lineno}: unknown material {name:?}, using the default one",
+                            path.display()
+                        ));
+                        Arc::clone(&default_material)
+                    }
+                };
+            }
+            // groups, objects, smoothing groups, lines…
+            _ => (),
+        }
+    }
+
+    Ok((triangles, warnings))
+}
+
+fn parse_f64(s: &str) -> Result<f64, String> {
+    s.parse().map_err(|e| format!("invalid number {s:?}: {e}"))
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+    // `v` can have an optional 4th component (w), which we ignore
+    if args.len() < 3 {
+        return Err(format!("expected 3 coordinates, got {}", args.len()));
+    }
+    Ok(Vec3::from([
+        parse_f64(args[0])?,
+        parse_f64(args[1])?,
+        parse_f64(args[2])?,
+    ]))
+}
+
+fn parse_uv(args: &[&str]) -> Result<(f64, f64), String> {
+    // the v coordinate is optional, and there can be a 3rd (w) one we ignore
+    match args {
+        [] => Err("expected texture coordinates".to_string()),
+        [u] => Ok((parse_f64(u)?, 0.0)),
+        [u, v, ..] => Ok((parse_f64(u)?, parse_f64(v)?)),
+    }
+}
+
+/// resolve an obj index, which is 1-based and can be negative (relative
+/// to the end of the list) into a 0-based index
+fn resolve_index(s: &str, len: usize) -> Result<usize, String> {
+    let idx: isize = s.parse().map_err(|e| format!("invalid index {s:?}: {e}"))?;
+    let resolved = if idx > 0 { idx - 1 } else { len as isize + idx };
+    if idx == 0 || resolved < 0 || resolved as usize >= len {
+        return Err(format!("index {idx} out of range (only {len} elements)"));
+    }
+    Ok(resolved as usize)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// a face corner looks like `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+fn parse_corner(
+    arg: &str,
+    n_positions: usize,
+    n_texcoords: usize,
+    n_normals: usize,
+) -> Result<Corner, String> {
+    let mut parts = arg.split('/');
+    let position = resolve_index(parts.next().unwrap_or_default(), n_positions)?;
+    let texcoord = match parts.next() {
+        Some(s) if !s.is_empty() => Some(resolve_index(s, n_texcoords)?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(s) if !s.is_empty() => Some(resolve_index(s, n_normals)?),
+        _ => None,
+    };
+    Ok(Corner {
+        position,
+        texcoord,
+        normal,
+    })
+}
+
+/// parse a material library, mapping the phong-ish materials onto ours
+/// as well as we can.
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<Material>>, ObjError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| ObjError::Io(path.to_path_buf(), err))?;
+    let parse_err = |line: usize, msg: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        msg,
+    };
+
+    let mut defs: Vec<(String, MtlDef)> = vec![];
+    for (idx, line) in content.lines().enumerate() {
+        let lineno = idx + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args = tokens.collect::<Vec<_>>();
+
+        if keyword == "newmtl" {
+            defs.push((args.join(" "), MtlDef::default()));
+            continue;
+        }
+
+        let Some((_, def)) = defs.last_mut() else {
+            return Err(parse_err(lineno, format!("{keyword} before any newmtl")));
+        };
+        let parse_color = |args: &[&str]| parse_vec3(args).map_err(|msg| parse_err(lineno, msg));
+        let parse_scalar = |args: &[&str]| match args.first() {
+            Some(s) => parse_f64(s).map_err(|msg| parse_err(lineno, msg)),
+            None => Err(parse_err(lineno, format!("missing value for {keyword}"))),
+        };
+        match keyword {
+            "Kd" => def.diffuse = parse_color(&args)?,
+            "Ks" => def.specular = parse_color(&args)?,
+            "Ke" => def.emission = parse_color(&args)?,
+            "Ns" => def.shininess = parse_scalar(&args)?,
+            "Ni" => def.ior = parse_scalar(&args)?,
+            "d" => def.dissolve = parse_scalar(&args)?,
+            "Tr" => def.dissolve = 1.0 - parse_scalar(&args)?,
+            "illum" => def.illum = parse_scalar(&args)? as u32,
+            // options (like -bm) aren't supported, the file name is the last argument
+            "map_Kd" => match args.last() {
+                Some(name) => {
+                    def.diffuse_map = Some(path.parent().unwrap_or(Path::new(".")).join(name))
+                }
+                None => return Err(parse_err(lineno, "missing file for map_Kd".to_string())),
+            },
+            _ => (),
+        }
+    }
+
+    defs.into_iter()
+        .map(|(name, def)| Ok((name, Arc::new(def.to_material()?))))
+        .collect()
+}
+
+/// the subset of mtl properties we can do something with
+#[derive(Debug)]
+struct MtlDef {
+    diffuse: Color,
+    diffuse_map: Option<PathBuf>,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    ior: f64,
+    dissolve: f64,
+    illum: u32,
+}
+
+impl Default for MtlDef {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::from([0.8, 0.8, 0.8]),
+            diffuse_map: None,
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlDef {
+    fn to_material(&self) -> Result<Material, ObjError> {
+        let material = if !self.emission.is_near_zero() {
+            Material::DiffuseLight {
+                emit: self.emission,
+            }
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Material::Dielectric { ir: self.ior }
+        } else if self.illum == 3 || self.illum == 5 {
+            // map the phong exponent to a roughness
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().clamp(0.0, 1.0);
+            Material::Metal {
+                albedo: self.specular,
+                fuzz,
+            }
+        } else if let Some(path) = &self.diffuse_map {
+            let img =
+                ImageTexture::load(path).map_err(|err| ObjError::Texture(path.clone(), err))?;
+            Material::Lambertian {
+                albedo: Texture::Image(Arc::new(img)),
+            }
+        } else {
+            Material::Lambertian {
+                albedo: Texture::Solid(self.diffuse),
+            }
+        };
+        Ok(material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(content: &str) -> Result<Vec<Triangle>, ObjError> {
+        let default = Material::Lambertian {
+            albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+        };
+        parse(content, Path::new("test.obj"), &default).map(|(triangles, _)| triangles)
+    }
+
+    const SQUARE: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    #[test]
+    fn quads_are_split_in_fans() {
+        let triangles = parse_str(&format!("{SQUARE}f 1 2 3 4\n")).unwrap();
+        let [p0, p1, p2, p3] = [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]].map(Vec3::from);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].vertices, [p0, p1, p2]);
+        assert_eq!(triangles[1].vertices, [p0, p2, p3]);
+
+        // and any polygon
+        let triangles = parse_str(&format!("{SQUARE}v 0.5 2 0\nf 1 2 3 5 4\n")).unwrap();
+        assert_eq!(triangles.len(), 3);
+    }
+
+    #[test]
+    fn negative_indices() {
+        let relative = parse_str(&format!("{SQUARE}f -4 -3 -2\n")).unwrap();
+        let absolute = parse_str(&format!("{SQUARE}f 1 2 3\n")).unwrap();
+        assert_eq!(relative[0].vertices, absolute[0].vertices);
+
+        // relative to the vertices declared so far, not to the whole file
+        let triangles = parse_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\n").unwrap();
+        assert_eq!(triangles[0].vertices[2], Vec3::from([0, 1, 0]));
+    }
+
+    #[test]
+    fn corner_forms() {
+        let content = format!(
+            "{SQUARE}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\nf 1//1 2//1 3//1\nf 1/1 2/2 3/3\nf 1 2 3\n"
+        );
+        let triangles = parse_str(&content).unwrap();
+        assert_eq!(triangles.len(), 4);
+
+        let n = Vec3::from([0, 0, 1]);
+        assert_eq!(triangles[0].uvs, Some([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]));
+        assert_eq!(triangles[0].normals, Some([n, n, n]));
+        assert_eq!(triangles[1].uvs, None);
+        assert_eq!(triangles[1].normals, Some([n, n, n]));
+        assert_eq!(triangles[2].uvs, triangles[0].uvs);
+        assert_eq!(triangles[2].normals, None);
+        assert_eq!(triangles[3].uvs, None);
+        assert_eq!(triangles[3].normals, None);
+    }
+
+    #[test]
+    fn invalid_indices() {
+        for face in ["f 1 2 5", "f 0 1 2", "f -5 1 2", "f 1/2 2 3", "f 1 2"] {
+            match parse_str(&format!("{SQUARE}{face}\n")) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, 6, "{face}"),
+                other => panic!("{face} should be rejected, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_material() {
+        let default = Material::Lambertian {
+            albedo: Texture::Solid(Color::from([0.5, 0.5, 0.5])),
+        };
+        let content = format!("{SQUARE}usemtl gold\nf 1 2 3\n");
+        let (triangles, warnings) = parse(&content, Path::new("test.obj"), &default).unwrap();
+        assert_eq!(*triangles[0].material, default);
+        assert_eq!(
+            warnings,
+            ["test.obj:6: unknown material \"gold\", using the default one"]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    light::Light,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    vec3::{Point3, Vec3},
+};
+
+/// a parallelogram with a corner at `q` and its sides along `u` and `v`.
+/// The front face is the one `u × v` points to.
+#[derive(Debug)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    /// unit normal of the plane containing the quad
+    normal: Vec3,
+    /// used to find the coordinates of a point of the plane along u and v
+    w: Vec3,
+    material: Arc<Material>,
+}
+
+/// the plane containing an axis aligned rectangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plane {
+    Xy,
+    Xz,
+    Yz,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<Material>) -> Self {
+        let n = u.cross(&v);
+        Self {
+            q,
+            u,
+            v,
+            normal: n.unit(),
+            w: n / n.length_squared(),
+            material,
+        }
+    }
+
+    /// rectangle in `plane` at the coordinate `k` along the remaining axis,
+    /// going from `min` to `max` (in the order of the plane's name). Its
+    /// front face looks towards the positive side of the remaining axis.
+    pub fn rect(
+        plane: Plane,
+        min: (f64, f64),
+        max: (f64, f64),
+        k: f64,
+        material: Arc<Material>,
+    ) -> Self {
+        let (a0, b0) = min;
+        let (a1, b1) = max;
+        let (q, u, v) = match plane {
+            Plane::Xy => (
+                Vec3::from([a0, b0, k]),
+                Vec3::from([a1 - a0, 0.0, 0.0]),
+                Vec3::from([0.0, b1 - b0, 0.0]),
+            ),
+            Plane::Xz => (
+                Vec3::from([a0, k, b0]),
+                Vec3::from([0.0, 0.0, b1 - b0]),
+                Vec3::from([a1 - a0, 0.0, 0.0]),
+            ),
+            Plane::Yz => (
+                Vec3::from([k, a0, b0]),
+                Vec3::from([0.0, a1 - a0, 0.0]),
+                Vec3::from([0.0, 0.0, b1 - b0]),
+            ),
+        };
+        Self::new(q, u, v, material)
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&ray.dir);
+        // parallel to the plane
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.q - ray.orig)) / denom;
+        if t < tmin || tmax < t {
+            return None;
+        }
+
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(HitRecord::new(
+            p,
+            self.normal,
+            t,
+            (alpha, beta),
+            ray,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let (min, max) = corners
+            .iter()
+            .fold((self.q, self.q), |(min, max), c| (min.min(c), max.max(c)));
+        // the quad has no thickness when it's aligned with an axis
+        let pad = Vec3::from([1e-6, 1e-6, 1e-6]);
+        Aabb::new(min - pad, max + pad)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if !self.material.is_emissive() {
+            return vec![];
+        }
+        vec![Light::Quad {
+            q: self.q,
+            u: self.u,
+            v: self.v,
+        }]
+    }
+}
+
+/// axis aligned box, made of six quads facing outward
+#[derive(Debug)]
+pub struct BoxShape {
+    sides: Vec<Quad>,
+}
+
+impl BoxShape {
+    /// box between the two opposite corners `a` and `b`
+    pub fn new(a: Point3, b: Point3, material: Arc<Material>) -> Self {
+        let min = a.min(&b);
+        let max = a.max(&b);
+        let dx = Vec3::from([max.x - min.x, 0.0, 0.0]);
+        let dy = Vec3::from([0.0, max.y - min.y, 0.0]);
+        let dz = Vec3::from([0.0, 0.0, max.z - min.z]);
+
+        let side = |q: [f64; 3], u: Vec3, v: Vec3| Quad::new(Vec3::from(q), u, v, material.clone());
+        let sides = vec![
+            side([min.x, min.y, max.z], dx, dy),  // front
+            side([max.x, min.y, max.z], -dz, dy), // right
+            side([max.x, min.y, min.z], -dx, dy), // back
+            side([min.x, min.y, min.z], dz, dy),  // left
+            side([min.x, max.y, max.z], dx, -dz), // top
+            side([min.x, min.y, min.z], dx, dz),  // bottom
+        ];
+        Self { sides }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        self.sides.hit(ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.sides.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.sides.lights()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ray::Face, texture::Texture};
+
+    fn material() -> Arc<Material> {
+        Arc::new(Material::Lambertian {
+            albedo: Texture::Solid(Vec3::from([0.5, 0.5, 0.5])),
+        })
+    }
+
+    fn ray(orig: impl Into<Vec3>, dir: impl Into<Vec3>) -> Ray {
+        Ray {
+            orig: orig.into(),
+            dir: dir.into(),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn quad_hit_and_uv() {
+        // 2 by 1, tilted, so that u and v aren't the same thing as x and y
+        let quad = Quad::new(
+            Vec3::from([1, 0, 0]),
+            Vec3::from([2, 0, 0]),
+            Vec3::from([0, 1, 1]),
+            material(),
+        );
+        let hit = quad
+            .hit(&ray([2.5, 0.25, 5.0], [0, 0, -1]), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 4.75).abs() < 1e-12);
+        assert!((hit.u - 0.75).abs() < 1e-12 && (hit.v - 0.25).abs() < 1e-12);
+        assert!((hit.p - Vec3::from([2.5, 0.25, 0.25])).length() < 1e-12);
+
+        // outside of the sides, and beyond tmax
+        for orig in [
+            [0.5, 0.5, 5.0],
+            [3.5, 0.5, 5.0],
+            [2.0, -0.5, 5.0],
+            [2.0, 1.5, 5.0],
+        ] {
+            assert!(quad
+                .hit(&ray(orig, [0, 0, -1]), 0.0, f64::INFINITY)
+                .is_none());
+        }
+        assert!(quad
+            .hit(&ray([2.5, 0.25, 5.0], [0, 0, -1]), 0.0, 4.0)
+            .is_none());
+        // parallel to the plane
+        assert!(quad
+            .hit(&ray([2.0, 0.0, 0.0], [0, 1, 1]), 0.0, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn quad_faces() {
+        // u × v is +z
+        let quad = Quad::rect(Plane::Xy, (0.0, 0.0), (1.0, 1.0), 0.0, material());
+        let front = quad
+            .hit(&ray([0.5, 0.5, 1.0], [0, 0, -1]), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_eq!(front.face, Face::Front);
+        assert_eq!(front.normal, Vec3::from([0, 0, 1]));
+        let back = quad
+            .hit(&ray([0.5, 0.5, -1.0], [0, 0, 1]), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_eq!(back.face, Face::Back);
+        // always against the ray
+        assert_eq!(back.normal, Vec3::from([0, 0, -1]));
+    }
+
+    #[test]
+    fn rects_face_the_positive_side() {
+        for (plane, dir) in [
+            (Plane::Xy, [0, 0, -1]),
+            (Plane::Xz, [0, -1, 0]),
+            (Plane::Yz, [-1, 0, 0]),
+        ] {
+            let rect = Quad::rect(plane, (0.0, 0.0), (1.0, 2.0), 0.0, material());
+            let orig = -3.0 * Vec3::from(dir) + Vec3::from([0.5, 0.5, 0.5]);
+            let hit = rect.hit(&ray(orig, dir), 0.0, f64::INFINITY).unwrap();
+            assert_eq!(hit.face, Face::Front, "{plane:?}");
+            assert!((hit.t - 3.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn box_hits() {
+        // the corners can be given in any order
+        let b = BoxShape::new(Vec3::from([1, 2, 3]), Vec3::from([-1, -2, -3]), material());
+
+        // from outside, on each side, the normals point outward
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut orig = [0.1, 0.2, 0.3];
+                orig[axis] = sign * 10.0;
+                let mut dir = [0.0; 3];
+                dir[axis] = -sign;
+                let dir = Vec3::from(dir);
+                let hit = b.hit(&ray(orig, dir), 0.0, f64::INFINITY).unwrap();
+                let extent = [1.0, 2.0, 3.0][axis];
+                assert!((hit.t - (10.0 - extent)).abs() < 1e-12);
+                assert_eq!(hit.face, Face::Front);
+                assert_eq!(hit.normal, -dir);
+            }
+        }
+
+        // from inside, the back of the sides
+        let hit = b
+            .hit(&ray([0.0, 0.0, 0.0], [0, 1, 0]), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert_eq!(hit.face, Face::Back);
+        assert_eq!(hit.normal, Vec3::from([0, -1, 0]));
+
+        // and missing it
+        assert!(b
+            .hit(&ray([0.0, 5.0, 0.0], [1, 0, 0]), 0.0, f64::INFINITY)
+            .is_none());
+    }
+}
@@ -1,38 +1,49 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::light::Light;
 use crate::material::Material;
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Debug)]
-pub(crate) struct Ray {
-    pub(crate) orig: Point3,
-    pub(crate) dir: Vec3,
+pub struct Ray {
+    pub orig: Point3,
+    pub dir: Vec3,
+    /// when the ray is cast, between the opening and the closing of the
+    /// shutter. Moving objects are hit where they are at that time.
+    pub time: f64,
 }
 
 impl Ray {
-    pub(crate) fn at(&self, t: f64) -> Point3 {
+    pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
 }
 
-#[derive(Debug)]
-pub(crate) enum Face {
+#[derive(Debug, PartialEq)]
+pub enum Face {
     Front,
     Back,
 }
 
-#[derive(Debug)]
-pub(crate) struct HitRecord<'a> {
-    pub(crate) p: Point3,
-    pub(crate) normal: Vec3,
-    pub(crate) t: f64,
-    pub(crate) face: Face,
-    pub(crate) mat: &'a Material,
+#[derive(Debug, PartialEq)]
+pub struct HitRecord<'a> {
+    pub p: Point3,
+    pub normal: Vec3,
+    pub t: f64,
+    /// surface coordinates of the hit point, used for textures
+    pub u: f64,
+    pub v: f64,
+    pub face: Face,
+    pub mat: &'a Material,
 }
 
 impl<'a> HitRecord<'a> {
-    pub(crate) fn new(
+    pub fn new(
         p: Point3,
         outward_normal: Vec3,
         t: f64,
+        (u, v): (f64, f64),
         ray: &Ray,
         mat: &'a Material,
     ) -> Self {
@@ -45,12 +56,77 @@ impl<'a> HitRecord<'a> {
             p,
             normal,
             t,
+            u,
+            v,
             face,
             mat,
         }
     }
 }
 
-pub(crate) trait Hittable {
-    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord>;
+pub trait Hittable {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>>;
+
+    /// a box enclosing the whole object, used to build the bounding volume
+    /// hierarchy.
+    fn bounding_box(&self) -> Aabb;
+
+    /// the emissive parts of the object, which can be sampled directly
+    fn lights(&self) -> Vec<Light> {
+        vec![]
+    }
+}
+
+/// any kind of object, so that they can be mixed in the same world
+pub type Object = Box<dyn Hittable + Send + Sync>;
+
+impl<T> Hittable for &T
+where
+    T: Hittable + ?Sized,
+{
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        (**self).lights()
+    }
+}
+
+impl<T> Hittable for Arc<T>
+where
+    T: Hittable + ?Sized,
+{
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        (**self).lights()
+    }
+}
+
+impl<T> Hittable for Box<T>
+where
+    T: Hittable + ?Sized,
+{
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        (**self).lights()
+    }
 }
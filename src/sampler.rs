@@ -0,0 +1,545 @@
+//! Where the random numbers of the renderer come from. Each sample of a pixel
+//! is a point in a high dimensional unit cube, one dimension being used for
+//! each random decision along the path (where in the pixel, where on the
+//! lens, which direction to scatter to...). Spreading these points evenly
+//! instead of picking them independently makes the image converge faster.
+//!
+//! The samples only depend on the seed of the render, the pixel and the
+//! index of the sample, so that the same render gives the same image
+//! whatever the order in which the pixels are computed.
+
+use std::{f64::consts::PI, fmt::Display};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::vec3::Vec3;
+
+pub trait Sampler {
+    /// go back to the first dimension, for the `index`th sample of a pixel
+    fn start_sample(&mut self, x: usize, y: usize, index: usize);
+
+    /// next dimension of the current sample, in [0;1)
+    fn next_1d(&mut self) -> f64;
+
+    /// next two dimensions of the current sample, which are well distributed
+    /// together (for pixel positions, directions...)
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// independent random numbers, the reference
+    Independent,
+    /// jittered samples, one per stratum of each dimension
+    Stratified,
+    /// Halton sequence, randomized per pixel
+    Halton,
+    /// Sobol sequence with Owen scrambling
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// a sampler for images with `samples_per_pixel` samples, `seed` making
+    /// the samples of different renders different
+    pub fn create(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(Independent::new(seed)),
+            SamplerKind::Stratified => Box::new(Stratified::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(Halton::new(seed)),
+            SamplerKind::Sobol => Box::new(Sobol::new(seed)),
+        }
+    }
+}
+
+impl Display for SamplerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// splitmix64's finalizer, scrambling all the bits of `x`
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// hash of a few values, for seeds which look unrelated to each other
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |h, v| {
+        mix(h.wrapping_add(*v).wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+/// the high bits of a hash as a number in [0;1)
+fn to_unit(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// a random looking number in [0;1) which is always the same for the same
+/// `values`, for the random decisions which can't be made by a sampler
+pub fn hash_to_unit(values: &[u64]) -> f64 {
+    to_unit(hash(values))
+}
+
+#[derive(Debug)]
+pub struct Independent {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        let seed = hash(&[self.seed, x as u64, y as u64, index as u64]);
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+/// Each dimension (or pair of dimensions) is split into as many strata as
+/// there are samples per pixel, and each sample of a pixel falls in a
+/// different stratum, in a random order.
+#[derive(Debug)]
+struct Stratified {
+    samples_per_pixel: usize,
+    seed: u64,
+    pixel: u64,
+    index: usize,
+    dimension: u64,
+}
+
+impl Stratified {
+    fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// stratum of the current sample, among `count`
+    fn stratum(&self, count: usize) -> usize {
+        let seed = hash(&[self.pixel, self.dimension]) as u32;
+        permute((self.index % count) as u32, count as u32, seed) as usize
+    }
+
+    fn jitter(&self, n: u64) -> f64 {
+        to_unit(hash(&[self.pixel, self.dimension, self.index as u64, n]))
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let u = (self.stratum(n) as f64 + self.jitter(0)) / n as f64;
+        self.dimension += 1;
+        u
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        // a grid with at least as many cells as samples
+        let nx = (self.samples_per_pixel as f64).sqrt().ceil() as usize;
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let cell = self.stratum(nx * ny);
+        let u = (
+            ((cell % nx) as f64 + self.jitter(0)) / nx as f64,
+            ((cell / nx) as f64 + self.jitter(1)) / ny as f64,
+        );
+        self.dimension += 2;
+        u
+    }
+}
+
+/// element `i` of a random permutation of [0;len), picked by `seed`. From
+/// "Correlated Multi-Jittered Sampling" (Kensler, 2013).
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    // permute within the next power of 2, until landing inside the range
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % len
+}
+
+/// bases of the dimensions of the Halton sequence, the ones after are
+/// independent random numbers
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The same Halton sequence for every pixel, shifted by a random offset in
+/// each dimension (Cranley-Patterson rotation) so that the pixels don't all
+/// get the same samples.
+#[derive(Debug)]
+struct Halton {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl Halton {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+/// digits of `index` in `base`, mirrored around the decimal point
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0;
+    while index > 0 {
+        reversed = reversed * base + index % base;
+        inv_base_n *= inv_base;
+        index /= base;
+    }
+    (reversed as f64 * inv_base_n).min(1.0 - f64::EPSILON)
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let offset = to_unit(hash(&[self.pixel, self.dimension]));
+        let u = match PRIMES.get(self.dimension as usize) {
+            Some(base) => (radical_inverse(*base, self.index) + offset).fract(),
+            None => to_unit(hash(&[self.pixel, self.dimension, self.index])),
+        };
+        self.dimension += 1;
+        u
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// direction numbers of the first 4 dimensions of the Sobol sequence, from
+/// the primitive polynomials and initial numbers of Joe and Kuo
+const SOBOL_DIRECTIONS: [[u32; 32]; 4] = {
+    // (degree, coefficients, initial direction numbers)
+    let params: [(usize, u32, [u32; 3]); 3] =
+        [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+    let mut directions = [[0; 32]; 4];
+    let mut k = 0;
+    while k < 32 {
+        directions[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+    let mut dim = 1;
+    while dim < 4 {
+        let (s, a, m) = params[dim - 1];
+        let v = &mut directions[dim];
+        let mut k = 0;
+        while k < 32 {
+            if k < s {
+                v[k] = m[k] << (31 - k);
+            } else {
+                v[k] = v[k - s] ^ (v[k - s] >> s);
+                let mut j = 1;
+                while j < s {
+                    if (a >> (s - 1 - j)) & 1 == 1 {
+                        v[k] ^= v[k - j];
+                    }
+                    j += 1;
+                }
+            }
+            k += 1;
+        }
+        dim += 1;
+    }
+    directions
+};
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let mut x = 0;
+    for (bit, direction) in SOBOL_DIRECTIONS[dimension].iter().enumerate() {
+        if (index >> bit) & 1 == 1 {
+            x ^= direction;
+        }
+    }
+    x
+}
+
+/// Owen scrambling: random permutations of the digits, each one depending
+/// on the digits before it. From "Practical Hash-based Owen Scrambling"
+/// (Burley, 2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// The dimensions go by groups of 4 taken from the same 4D Sobol point, the
+/// groups being decorrelated by shuffling the order of the points (scrambling
+/// the index) with a different seed.
+#[derive(Debug)]
+struct Sobol {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+    group: [f64; 4],
+}
+
+impl Sobol {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+            group: [0.0; 4],
+        }
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        if self.dimension.is_multiple_of(4) {
+            let seed = hash(&[self.pixel, (self.dimension / 4) as u64]);
+            let index = nested_uniform_scramble(self.index, seed as u32);
+            for (dim, u) in self.group.iter_mut().enumerate() {
+                let seed = hash(&[seed, dim as u64]) as u32;
+                let x = nested_uniform_scramble(sobol(index, dim), seed);
+                *u = x as f64 / (1u64 << 32) as f64;
+            }
+        }
+        let u = self.group[self.dimension % 4];
+        self.dimension += 1;
+        u
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        // the pairs (0, 1) and (2, 3) are the best distributed ones
+        if self.dimension % 2 == 1 {
+            self.dimension += 1;
+        }
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// uniform direction
+pub fn uniform_sphere((u, v): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::from([r * phi.cos(), r * phi.sin(), z])
+}
+
+/// uniform point in the unit ball, `w` picking the distance to the center
+pub fn uniform_ball(u: (f64, f64), w: f64) -> Vec3 {
+    w.cbrt() * uniform_sphere(u)
+}
+
+/// uniform point in the unit disk (z = 0), keeping the points which are
+/// close in the square close in the disk (Shirley and Chiu's concentric
+/// mapping)
+pub fn concentric_disk((u, v): (f64, f64)) -> Vec3 {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::default();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::from([r * theta.cos(), r * theta.sin(), 0.0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permutations_are_bijections() {
+        for len in [1, 2, 7, 16, 100] {
+            for seed in [0, 1, 0xdeadbeef] {
+                let mut seen = vec![false; len as usize];
+                for i in 0..len {
+                    let p = permute(i, len, seed);
+                    assert!(!seen[p as usize], "{p} appears twice");
+                    seen[p as usize] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_only_depend_on_the_pixel_and_index() {
+        let pixels = [(0, 0, 0), (3, 1, 0), (3, 1, 5), (0, 0, 1), (7, 2, 3)];
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.create(8, 42);
+            let mut draw = |(x, y, index)| {
+                sampler.start_sample(x, y, index);
+                let (a, b) = sampler.next_2d();
+                [
+                    a,
+                    b,
+                    sampler.next_1d(),
+                    sampler.next_1d(),
+                    sampler.next_1d(),
+                ]
+            };
+            let forward = pixels.map(&mut draw);
+            let mut backward = pixels;
+            backward.reverse();
+            let mut backward = backward.map(&mut draw);
+            backward.reverse();
+            assert_eq!(forward, backward, "{kind}");
+
+            let mut other_seed = kind.create(8, 43);
+            other_seed.start_sample(3, 1, 0);
+            assert_ne!(other_seed.next_1d(), forward[1][0], "{kind}");
+        }
+    }
+
+    #[test]
+    fn sobol_points_are_stratified() {
+        // the first 2^k points of each pair of dimensions have one point in
+        // each of the 2^k elementary intervals of a given shape, scrambled
+        // or not
+        let mut sobol = Sobol::new(3);
+        let n = 64;
+        for (cols, rows) in [(64, 1), (8, 8), (1, 64), (4, 16)] {
+            let mut cells = vec![0; n];
+            for i in 0..n {
+                sobol.start_sample(5, 7, i);
+                let (u, v) = sobol.next_2d();
+                let cell = (v * rows as f64) as usize * cols + (u * cols as f64) as usize;
+                cells[cell] += 1;
+            }
+            assert!(cells.iter().all(|c| *c == 1), "{cols}x{rows}: {cells:?}");
+        }
+    }
+
+    /// integrate the function with each sampler, the low discrepancy ones
+    /// should get closer to the exact value
+    #[test]
+    fn samplers_integrate_better_than_random() {
+        // a smooth function whose integral over the 4D cube is 1
+        let f = |u: [f64; 4]| u.iter().map(|x| PI / 2.0 * (PI * x).sin()).product::<f64>();
+        let exact = 1.0;
+        let n = 256;
+        let error = |kind: SamplerKind| {
+            let mut total_error = 0.0;
+            for pixel in 0..16 {
+                let mut sampler = kind.create(n, 1);
+                let mut sum = 0.0;
+                for i in 0..n {
+                    sampler.start_sample(pixel, 0, i);
+                    let (a, b) = sampler.next_2d();
+                    let (c, d) = sampler.next_2d();
+                    sum += f([a, b, c, d]);
+                }
+                total_error += (sum / n as f64 - exact).abs();
+            }
+            total_error / 16.0
+        };
+        let random = error(SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let err = error(kind);
+            assert!(
+                err < random / 2.0,
+                "{kind} error is {err}, vs {random} for random numbers"
+            );
+        }
+    }
+}
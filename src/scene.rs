@@ -0,0 +1,917 @@
+//! Scene description files, in TOML. A scene is made of a camera, a set of
+//! named materials and a list of objects referencing these materials:
+//!
+//! ```toml
+//! background = [0.0, 0.0, 0.0] # or "gradient", the default
+//! # or a lat-long environment map, rotated around the vertical axis (in
+//! # degrees), lighting the scene
+//! # background = { path = "sky.hdr", rotation = 90, intensity = 1.0 }
+//! # or a daylight sky, with the angles of the sun in degrees (an azimuth of
+//! # 0 is towards +x, 90 towards -z) and a turbidity from 2 (clear) to 10
+//! # background = { sun_elevation = 30, sun_azimuth = 90, turbidity = 3.0 }
+//!
+//! [camera]
+//! look_from = [13, 2, 3]
+//! look_at = [0, 0, 0]
+//! vfov = 20.0
+//! aperture = 0.1
+//! shutter = [0, 1] # the default, moving objects are blurred over this time
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = [0.5, 0.5, 0.5]
+//!
+//! [materials.floor]
+//! type = "lambertian"
+//! # or "image" with a path, or "noise" with a scale
+//! albedo = { type = "checker", scale = 0.5, even = [0, 0, 0], odd = [1, 1, 1] }
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [0, -1000, 0]
+//! radius = 1000
+//! material = "ground"
+//!
+//! # "moving_sphere" goes from center0 at time0 (default 0) to center1 at
+//! # time1 (default 1)
+//! [[objects]]
+//! type = "moving_sphere"
+//! center0 = [0, 1, 0]
+//! center1 = [0, 1.5, 0]
+//! radius = 0.5
+//! material = "ground"
+//!
+//! # also "triangle", "quad", "rect", "box" and "mesh"
+//! [[objects]]
+//! type = "rect"
+//! plane = "xz"
+//! min = [-1, -1]
+//! max = [1, 1]
+//! k = 3
+//! material = "ground"
+//! # optional, applied in order. Also "rotate_x", "rotate_z" and
+//! # "rotate" with an axis and an angle
+//! transform = [{ scale = 2 }, { rotate_y = 45 }, { translate = [0, 1, 0] }]
+//!
+//! [materials.fog]
+//! type = "isotropic"
+//! albedo = [1, 1, 1]
+//!
+//! # an object with a density is a volume filling its shape, which must be
+//! # convex, with an isotropic material
+//! [[objects]]
+//! type = "box"
+//! min = [-5, 0, -5]
+//! max = [5, 2, 5]
+//! material = "fog"
+//! density = 0.1
+//! ```
+//!
+//! Meshes loaded several times with the same material are only loaded once,
+//! and shared between the objects.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    camera::CameraSettings,
+    environment::EnvironmentMap,
+    material::{ConstantMedium, Material, MovingSphere, Sphere},
+    mesh::{Mesh, Triangle},
+    obj,
+    quad::{BoxShape, Plane, Quad},
+    ray::Object,
+    sky::Sky,
+    texture::{ImageTexture, Perlin, Texture},
+    transform::{Transform, Transformed},
+    vec3::{Color, Vec3},
+    Background, World,
+};
+use serde::Deserialize;
+use toml::Spanned;
+
+/// a world and how to look at it
+pub struct Scene {
+    pub world: World,
+    pub camera: CameraSettings,
+    /// default image width, when not imposed by the output (window or command line)
+    pub image_width: Option<usize>,
+    pub aspect_ratio: Option<f64>,
+    /// what was ignored while loading the scene, like unknown materials in
+    /// mesh files
+    pub warnings: Vec<String>,
+}
+
+/// width of the images of the scenes which don't have one
+pub const DEFAULT_IMAGE_WIDTH: usize = 800;
+
+pub const DEFAULT_ASPECT_RATIO: f64 = 16.0 / 9.0;
+
+impl Scene {
+    /// size of the image, as given by the scene or from the defaults
+    pub fn image_size(&self) -> (usize, usize) {
+        let width = self.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH);
+        let aspect_ratio = self.aspect_ratio.unwrap_or(DEFAULT_ASPECT_RATIO);
+        let height = (width as f64 / aspect_ratio).round() as usize;
+        (width, height.max(1))
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    /// the file isn't valid TOML, or doesn't match the expected structure
+    Parse(toml::de::Error),
+    /// an element of the scene is invalid
+    Invalid {
+        line: usize,
+        field: String,
+        msg: String,
+    },
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "cannot read scene file: {err}"),
+            SceneError::Parse(err) => write!(f, "invalid scene file: {err}"),
+            SceneError::Invalid { line, field, msg } => {
+                write!(f, "invalid scene file at line {line}, in {field}: {msg}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    background: Option<Spanned<BackgroundDef>>,
+    camera: Option<Spanned<CameraDef>>,
+    // materials and objects are deserialized one by one afterward, to be able
+    // to report the location of errors, which is otherwise lost with tagged enums.
+    #[serde(default)]
+    materials: HashMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    objects: Vec<Spanned<toml::Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BackgroundDef {
+    Solid([f64; 3]),
+    Named(String),
+    Environment(EnvironmentDef),
+    Sky(SkyDef),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDef {
+    /// image file, relative to the scene file
+    path: PathBuf,
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "one")]
+    intensity: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDef {
+    sun_elevation: f64,
+    #[serde(default)]
+    sun_azimuth: f64,
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    #[serde(default = "one")]
+    intensity: f64,
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    vfov: Option<f64>,
+    focal_length: Option<f64>,
+    aperture: Option<f64>,
+    focus_dist: Option<f64>,
+    /// times at which the shutter opens and closes
+    shutter: Option<[f64; 2]>,
+    image_width: Option<usize>,
+    aspect_ratio: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDef {
+    Lambertian {
+        albedo: TextureDef,
+    },
+    Metal {
+        albedo: [f64; 3],
+        fuzz: f64,
+    },
+    Dielectric {
+        ir: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    /// scatters in all directions, for volumes
+    Isotropic {
+        albedo: TextureDef,
+    },
+}
+
+/// either a plain color, or a table describing a texture
+#[derive(Debug)]
+enum TextureDef {
+    Solid([f64; 3]),
+    Pattern(PatternDef),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PatternDef {
+    Checker {
+        scale: f64,
+        even: Box<TextureDef>,
+        odd: Box<TextureDef>,
+    },
+    /// image file, relative to the scene file
+    Image { path: PathBuf },
+    Noise {
+        scale: f64,
+        /// different seeds give different patterns
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+// done by hand instead of an untagged enum, to keep the error messages
+// from the texture definitions instead of a generic "no variant matched"
+impl<'de> Deserialize<'de> for TextureDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        let value = toml::Value::deserialize(deserializer)?;
+        let res = match value {
+            toml::Value::Array(_) => value.try_into().map(TextureDef::Solid),
+            toml::Value::Table(_) => value.try_into().map(TextureDef::Pattern),
+            _ => return Err(D::Error::custom("expected a color or a texture")),
+        };
+        res.map_err(|err| D::Error::custom(err.message()))
+    }
+}
+
+impl TextureDef {
+    fn to_texture(&self, base_dir: &Path) -> Result<Texture, String> {
+        let texture = match self {
+            TextureDef::Solid(color) => Texture::Solid(Color::from(*color)),
+            TextureDef::Pattern(PatternDef::Checker { scale, even, odd }) => Texture::Checker {
+                scale: *scale,
+                even: Box::new(even.to_texture(base_dir)?),
+                odd: Box::new(odd.to_texture(base_dir)?),
+            },
+            TextureDef::Pattern(PatternDef::Image { path }) => {
+                let path = base_dir.join(path);
+                let img = ImageTexture::load(&path)
+                    .map_err(|err| format!("cannot load {}: {err}", path.display()))?;
+                Texture::Image(Arc::new(img))
+            }
+            TextureDef::Pattern(PatternDef::Noise { scale, seed }) => Texture::Noise {
+                perlin: Arc::new(Perlin::new(*seed)),
+                scale: *scale,
+            },
+        };
+        Ok(texture)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDef {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    /// sphere going from `center0` at `time0` to `center1` at `time1`
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "one")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    /// parallelogram with a corner and two sides
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    /// axis aligned rectangle, `k` being the coordinate along the missing axis
+    Rect {
+        plane: PlaneDef,
+        min: [f64; 2],
+        max: [f64; 2],
+        k: f64,
+        material: String,
+    },
+    /// axis aligned box between two opposite corners
+    #[serde(rename = "box")]
+    BoxShape {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    /// wavefront obj file, relative to the scene file. The material is used
+    /// for the faces which don't have one in the obj file.
+    Mesh { path: PathBuf, material: String },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+/// one step of the transform of an object
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDef {
+    Translate([f64; 3]),
+    Scale(ScaleDef),
+    /// angles are in degrees
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate {
+        axis: [f64; 3],
+        angle: f64,
+    },
+}
+
+/// either the same factor for all the axes, or one per axis
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScaleDef {
+    Uniform(f64),
+    Axes([f64; 3]),
+}
+
+impl TransformDef {
+    fn to_transform(&self) -> Result<Transform, String> {
+        let transform = match self {
+            TransformDef::Translate(offset) => Transform::translation(&Vec3::from(*offset)),
+            TransformDef::Scale(scale) => {
+                let factors = match scale {
+                    ScaleDef::Uniform(f) => [*f, *f, *f],
+                    ScaleDef::Axes(factors) => *factors,
+                };
+                if factors.contains(&0.0) {
+                    return Err("scale factors cannot be 0".to_string());
+                }
+                Transform::scaling(&Vec3::from(factors))
+            }
+            TransformDef::RotateX(angle) => Transform::rotation(&Vec3::from([1, 0, 0]), *angle),
+            TransformDef::RotateY(angle) => Transform::rotation(&Vec3::from([0, 1, 0]), *angle),
+            TransformDef::RotateZ(angle) => Transform::rotation(&Vec3::from([0, 0, 1]), *angle),
+            TransformDef::Rotate { axis, angle } => {
+                if axis == &[0.0, 0.0, 0.0] {
+                    return Err("the rotation axis cannot be 0".to_string());
+                }
+                Transform::rotation(&Vec3::from(*axis), *angle)
+            }
+        };
+        Ok(transform)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PlaneDef {
+    Xy,
+    Xz,
+    Yz,
+}
+
+impl From<PlaneDef> for Plane {
+    fn from(plane: PlaneDef) -> Self {
+        match plane {
+            PlaneDef::Xy => Plane::Xy,
+            PlaneDef::Xz => Plane::Xz,
+            PlaneDef::Yz => Plane::Yz,
+        }
+    }
+}
+
+/// read and parse the given scene file
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let content = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+    parse(&content, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// parse a scene, with external files (like meshes) relative to `base_dir`
+pub fn parse(content: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let file: SceneFile = toml::from_str(content).map_err(SceneError::Parse)?;
+    let line_of = |span: std::ops::Range<usize>| content[..span.start].matches('\n').count() + 1;
+
+    let background = match file.background {
+        None => Background::Gradient,
+        Some(bg) => match bg.get_ref() {
+            BackgroundDef::Solid(color) => Background::Solid(Color::from(*color)),
+            BackgroundDef::Named(name) if name == "gradient" => Background::Gradient,
+            BackgroundDef::Named(other) => {
+                return Err(SceneError::Invalid {
+                    line: line_of(bg.span()),
+                    field: "background".to_string(),
+                    msg: format!(
+                        "unknown background {other:?}, expected \"gradient\", a color or an \
+                         environment map"
+                    ),
+                });
+            }
+            BackgroundDef::Environment(env) => {
+                let map =
+                    EnvironmentMap::load(&base_dir.join(&env.path), env.rotation, env.intensity)
+                        .map_err(|err| SceneError::Invalid {
+                            line: line_of(bg.span()),
+                            field: "background.path".to_string(),
+                            msg: err.to_string(),
+                        })?;
+                Background::Environment(Arc::new(map))
+            }
+            BackgroundDef::Sky(sky) => {
+                let invalid = |field: &str, msg: String| SceneError::Invalid {
+                    line: line_of(bg.span()),
+                    field: format!("background.{field}"),
+                    msg,
+                };
+                if !(-90.0..=90.0).contains(&sky.sun_elevation) {
+                    return Err(invalid(
+                        "sun_elevation",
+                        format!(
+                            "should be between -90 and 90 degrees, got {}",
+                            sky.sun_elevation
+                        ),
+                    ));
+                }
+                if !(2.0..=10.0).contains(&sky.turbidity) {
+                    return Err(invalid(
+                        "turbidity",
+                        format!("should be between 2 and 10, got {}", sky.turbidity),
+                    ));
+                }
+                let sky = Sky::new(
+                    sky.sun_elevation,
+                    sky.sun_azimuth,
+                    sky.turbidity,
+                    sky.intensity,
+                );
+                Background::Sky(Arc::new(sky))
+            }
+        },
+    };
+
+    let mut materials = HashMap::with_capacity(file.materials.len());
+    for (name, value) in file.materials {
+        let line = line_of(value.span());
+        let field = format!("materials.{name}");
+        let def = deserialize::<MaterialDef>(value.into_inner(), line, &field)?;
+        let material = match def {
+            MaterialDef::Lambertian { albedo } => Material::Lambertian {
+                albedo: albedo
+                    .to_texture(base_dir)
+                    .map_err(|msg| SceneError::Invalid {
+                        line,
+                        field: format!("{field}.albedo"),
+                        msg,
+                    })?,
+            },
+            MaterialDef::Metal { albedo, fuzz } => {
+                if !(0.0..=1.0).contains(&fuzz) {
+                    return Err(SceneError::Invalid {
+                        line,
+                        field: format!("{field}.fuzz"),
+                        msg: format!("fuzz should be between 0 and 1, got {fuzz}"),
+                    });
+                }
+                Material::Metal {
+                    albedo: Color::from(albedo),
+                    fuzz,
+                }
+            }
+            MaterialDef::Dielectric { ir } => Material::Dielectric { ir },
+            MaterialDef::DiffuseLight { emit } => Material::DiffuseLight {
+                emit: Color::from(emit),
+            },
+            MaterialDef::Isotropic { albedo } => Material::Isotropic {
+                albedo: albedo
+                    .to_texture(base_dir)
+                    .map_err(|msg| SceneError::Invalid {
+                        line,
+                        field: format!("{field}.albedo"),
+                        msg,
+                    })?,
+            },
+        };
+        materials.insert(name, material);
+    }
+
+    let lookup_material = |line: usize, field: &str, name: &str| {
+        materials.get(name).cloned().ok_or_else(|| {
+            let mut known = materials.keys().map(String::as_str).collect::<Vec<_>>();
+            known.sort_unstable();
+            SceneError::Invalid {
+                line,
+                field: format!("{field}.material"),
+                msg: format!(
+                    "unknown material {name:?}, known materials are: [{}]",
+                    known.join(", ")
+                ),
+            }
+        })
+    };
+
+    let mut warnings = vec![];
+    let mut meshes: HashMap<(PathBuf, String), Arc<Mesh>> = HashMap::new();
+    let mut objects = Vec::with_capacity(file.objects.len());
+    for (idx, value) in file.objects.into_iter().enumerate() {
+        let line = line_of(value.span());
+        let field = format!("objects[{idx}]");
+        let mut value = value.into_inner();
+
+        // common to all the kinds of objects, so handled separately
+        let mut transform = None;
+        if let Some(steps) = value.as_table_mut().and_then(|t| t.remove("transform")) {
+            let field = format!("{field}.transform");
+            let steps = deserialize::<Vec<TransformDef>>(steps, line, &field)?;
+            let mut combined = Transform::default();
+            for step in steps {
+                let step = step.to_transform().map_err(|msg| SceneError::Invalid {
+                    line,
+                    field: field.clone(),
+                    msg,
+                })?;
+                combined = combined.then(&step);
+            }
+            transform = Some(combined);
+        }
+        let mut density = None;
+        if let Some(value) = value.as_table_mut().and_then(|t| t.remove("density")) {
+            let field = format!("{field}.density");
+            let value = deserialize::<f64>(value, line, &field)?;
+            if value <= 0.0 {
+                return Err(SceneError::Invalid {
+                    line,
+                    field,
+                    msg: format!("the density should be positive, got {value}"),
+                });
+            }
+            density = Some(value);
+        }
+        // all the objects have one, the volumes need it once built
+        let material_name = value
+            .get("material")
+            .and_then(toml::Value::as_str)
+            .map(str::to_owned)
+            .unwrap_or_default();
+
+        let object: Object = match deserialize::<ObjectDef>(value, line, &field)? {
+            ObjectDef::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere {
+                center: Vec3::from(center),
+                radius,
+                material: lookup_material(line, &field, &material)?,
+            }),
+            ObjectDef::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Box::new(MovingSphere {
+                center0: Vec3::from(center0),
+                center1: Vec3::from(center1),
+                time0,
+                time1,
+                radius,
+                material: lookup_material(line, &field, &material)?,
+            }),
+            ObjectDef::Triangle { vertices, material } => Box::new(Triangle {
+                vertices: vertices.map(Vec3::from),
+                normals: None,
+                uvs: None,
+                material: Arc::new(lookup_material(line, &field, &material)?),
+            }),
+            ObjectDef::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => Box::new(Quad::new(
+                Vec3::from(corner),
+                Vec3::from(u),
+                Vec3::from(v),
+                Arc::new(lookup_material(line, &field, &material)?),
+            )),
+            ObjectDef::Rect {
+                plane,
+                min,
+                max,
+                k,
+                material,
+            } => Box::new(Quad::rect(
+                plane.into(),
+                (min[0], min[1]),
+                (max[0], max[1]),
+                k,
+                Arc::new(lookup_material(line, &field, &material)?),
+            )),
+            ObjectDef::BoxShape { min, max, material } => Box::new(BoxShape::new(
+                Vec3::from(min),
+                Vec3::from(max),
+                Arc::new(lookup_material(line, &field, &material)?),
+            )),
+            ObjectDef::Mesh { path, material } => {
+                let path = base_dir.join(path);
+                let key = (path, material);
+                let mesh = match meshes.get(&key) {
+                    Some(mesh) => Arc::clone(mesh),
+                    None => {
+                        let material = lookup_material(line, &field, &key.1)?;
+                        let (mesh, mesh_warnings) =
+                            obj::load(&key.0, &material).map_err(|err| SceneError::Invalid {
+                                line,
+                                field: format!("{field}.path"),
+                                msg: err.to_string(),
+                            })?;
+                        warnings.extend(mesh_warnings);
+                        let mesh = Arc::new(mesh);
+                        meshes.insert(key, Arc::clone(&mesh));
+                        mesh
+                    }
+                };
+                Box::new(mesh)
+            }
+        };
+        let object = match transform {
+            Some(transform) => Box::new(Transformed::new(object, transform)),
+            None => object,
+        };
+        // after the transform, so that the density doesn't get scaled
+        let object = match density {
+            Some(density) => {
+                let Material::Isotropic { albedo } = lookup_material(line, &field, &material_name)?
+                else {
+                    return Err(SceneError::Invalid {
+                        line,
+                        field: format!("{field}.material"),
+                        msg: format!(
+                            "objects with a density are volumes, their material \
+                             {material_name:?} should be isotropic"
+                        ),
+                    });
+                };
+                Box::new(ConstantMedium::new(object, density, albedo))
+            }
+            None => object,
+        };
+        objects.push(object);
+    }
+
+    let (cam, camera_line) = match file.camera {
+        Some(cam) => {
+            let line = line_of(cam.span());
+            (cam.into_inner(), line)
+        }
+        None => (CameraDef::default(), 1),
+    };
+    let invalid_camera = |field: &str, msg: String| SceneError::Invalid {
+        line: camera_line,
+        field: format!("camera.{field}"),
+        msg,
+    };
+    if let Some(vfov) = cam.vfov {
+        if vfov.is_nan() || vfov <= 0.0 || vfov >= 180.0 {
+            return Err(invalid_camera(
+                "vfov",
+                format!("should be strictly between 0 and 180 degrees, got {vfov}"),
+            ));
+        }
+    }
+    if let Some([open, close]) = cam.shutter {
+        if open.is_nan() || close.is_nan() || open > close {
+            return Err(invalid_camera(
+                "shutter",
+                format!("the shutter should open before it closes, got [{open}, {close}]"),
+            ));
+        }
+    }
+    if cam.image_width == Some(0) {
+        return Err(invalid_camera("image_width", "cannot be 0".to_string()));
+    }
+    if let Some(aspect_ratio) = cam.aspect_ratio {
+        if aspect_ratio.is_nan() || aspect_ratio <= 0.0 {
+            return Err(invalid_camera(
+                "aspect_ratio",
+                format!("should be positive, got {aspect_ratio}"),
+            ));
+        }
+    }
+
+    let default_camera = CameraSettings::default();
+    let camera = CameraSettings {
+        look_from: cam.look_from.map_or(default_camera.look_from, Vec3::from),
+        look_at: cam.look_at.map_or(default_camera.look_at, Vec3::from),
+        vup: cam.vup.map_or(default_camera.vup, Vec3::from),
+        vfov: cam.vfov.unwrap_or(default_camera.vfov),
+        focal_length: cam.focal_length.unwrap_or(default_camera.focal_length),
+        aperture: cam.aperture.unwrap_or(default_camera.aperture),
+        focus_dist: cam.focus_dist.or(default_camera.focus_dist),
+        shutter_open: cam.shutter.map_or(default_camera.shutter_open, |s| s[0]),
+        shutter_close: cam.shutter.map_or(default_camera.shutter_close, |s| s[1]),
+    };
+    // without a view direction, or with an up vector along it, the camera
+    // basis is made of NaNs and the image is black
+    let view = camera.look_from - camera.look_at;
+    if view.length() <= 1e-9 {
+        return Err(invalid_camera(
+            "look_at",
+            "should be different from look_from".to_string(),
+        ));
+    }
+    if camera.vup.cross(&view).length() <= 1e-9 * camera.vup.length() * view.length() {
+        return Err(invalid_camera(
+            "vup",
+            "should not be parallel to the view direction".to_string(),
+        ));
+    }
+
+    Ok(Scene {
+        world: World::new(objects).with_background(background),
+        camera,
+        image_width: cam.image_width,
+        aspect_ratio: cam.aspect_ratio,
+        warnings,
+    })
+}
+
+fn deserialize<T>(value: toml::Value, line: usize, field: &str) -> Result<T, SceneError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    value
+        .try_into()
+        .map_err(|err: toml::de::Error| SceneError::Invalid {
+            line,
+            field: field.to_string(),
+            msg: err.message().to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// line and field of the error reported for `content`
+    fn invalid(content: &str) -> (usize, String) {
+        match parse(content, Path::new(".")) {
+            Err(SceneError::Invalid { line, field, .. }) => (line, field),
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("the scene should be rejected"),
+        }
+    }
+
+    #[test]
+    fn valid_scene() {
+        let scene = parse(
+            r#"
+            [camera]
+            vfov = 40
+            image_width = 200
+            aspect_ratio = 2.0
+
+            [materials.ground]
+            type = "lambertian"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "ground"
+            "#,
+            Path::new("."),
+        )
+        .unwrap();
+        assert_eq!(scene.camera.vfov, 40.0);
+        assert_eq!(scene.image_width, Some(200));
+        assert_eq!(scene.aspect_ratio, Some(2.0));
+    }
+
+    #[test]
+    fn unknown_material() {
+        let content = r#"
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "grass"
+"#;
+        assert_eq!(invalid(content), (6, "objects[0].material".to_string()));
+    }
+
+    #[test]
+    fn invalid_material() {
+        let content = r#"
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.shiny]
+type = "metal"
+albedo = [0.5, 0.5, 0.5]
+fuzz = 2
+"#;
+        assert_eq!(invalid(content), (6, "materials.shiny.fuzz".to_string()));
+    }
+
+    #[test]
+    fn invalid_object() {
+        let content = r#"
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "ground"
+
+[[objects]]
+type = "sphere"
+center = [0, 0]
+radius = 1
+material = "ground"
+"#;
+        assert_eq!(invalid(content), (12, "objects[1]".to_string()));
+    }
+
+    #[test]
+    fn invalid_camera() {
+        for (setting, field) in [
+            ("vfov = 0", "camera.vfov"),
+            ("vfov = 180", "camera.vfov"),
+            ("vfov = -10", "camera.vfov"),
+            ("image_width = 0", "camera.image_width"),
+            ("aspect_ratio = 0", "camera.aspect_ratio"),
+            ("aspect_ratio = -1.5", "camera.aspect_ratio"),
+            ("aspect_ratio = nan", "camera.aspect_ratio"),
+            ("shutter = [1, 0.5]", "camera.shutter"),
+            (
+                "look_from = [1, 2, 3]\nlook_at = [1, 2, 3]",
+                "camera.look_at",
+            ),
+            ("look_at = [13, 2, 3]", "camera.look_at"),
+            (
+                "look_from = [0, 10, 0]\nlook_at = [0, 0, 0]\nvup = [0, 1, 0]",
+                "camera.vup",
+            ),
+            ("vup = [0, 0, 0]", "camera.vup"),
+        ] {
+            let content = format!("background = \"gradient\"\n\n[camera]\n{setting}\n");
+            assert_eq!(invalid(&content), (3, field.to_string()), "{setting}");
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Analytic daylight sky, from "A Practical Analytic Model for Daylight"
+//! (Preetham, Shirley and Smits, 1999), with the sun as a small disk which
+//! is sampled like the other lights.
+
+use std::f64::consts::PI;
+
+use crate::{
+    light::Light,
+    vec3::{Color, Vec3},
+};
+
+/// the sun seen from the earth, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+/// luminance of the sun before it goes through the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f64 = 2.0e6;
+
+/// the model gives luminances in kcd/m², scaled so that a white surface in
+/// full sun is around 1
+const LUMINANCE_SCALE: f64 = 1.0 / 20.0;
+
+#[derive(Debug)]
+pub struct Sky {
+    /// unit vector towards the sun
+    sun: Vec3,
+    /// Perez coefficients (A to E) of the luminance Y and the chromaticity
+    /// x and y
+    perez: [[f64; 5]; 3],
+    /// Y, x and y at the zenith, divided by the Perez function there so that
+    /// they only have to be multiplied by it
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    intensity: f64,
+}
+
+impl Sky {
+    /// `turbidity` goes from 2 (very clear) to 10 (hazy). The elevation
+    /// (above the horizon) and the azimuth are in degrees, an azimuth of 0
+    /// being towards +x and 90 towards -z.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, intensity: f64) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun = Vec3::from([
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.sin(),
+        ]);
+        // angle between the zenith and the sun
+        let theta_s = PI / 2.0 - elevation.max(0.0);
+        let t = turbidity;
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let turbidities = [t * t, t, 1.0];
+            (0..3)
+                .map(|i| turbidities[i] * (0..4).map(|j| m[i][j] * thetas[j]).sum::<f64>())
+                .sum::<f64>()
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_yy = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [zenith_y, zenith_x, zenith_yy];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_function(&perez[i], 0.0, theta_s));
+
+        Self {
+            sun,
+            perez,
+            zenith,
+            sun_radiance: sun_transmittance(theta_s, t) * SUN_LUMINANCE * LUMINANCE_SCALE,
+            intensity,
+        }
+    }
+
+    /// light coming from the direction `dir`. Below the horizon, this is the
+    /// light of the horizon.
+    pub fn color(&self, dir: &Vec3) -> Color {
+        let dir = dir.unit();
+        let cos_theta = dir.y.max(0.0);
+        let cos_gamma = dir.dot(&self.sun).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let [y, x, yy] = [0, 1, 2]
+            .map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta.acos(), gamma));
+        let mut color = xyy_to_rgb(x, yy, y * LUMINANCE_SCALE);
+        if self.sun.y > 0.0 && cos_gamma >= SUN_ANGULAR_RADIUS.cos() {
+            color += self.sun_radiance;
+        }
+        self.intensity * color
+    }
+
+    /// the sun, when it's above the horizon
+    pub fn light(&self) -> Option<Light> {
+        (self.sun.y > 0.0).then_some(Light::Sun {
+            direction: self.sun,
+            angular_radius: SUN_ANGULAR_RADIUS,
+        })
+    }
+}
+
+/// relative luminance (or chromaticity) of the sky at an angle `theta` from
+/// the zenith and `gamma` from the sun
+fn perez_function(coefs: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefs;
+    let cos_theta = theta.cos();
+    // straight towards the horizon, the exponential goes to 0
+    let horizon = if cos_theta > 0.0 {
+        (b / cos_theta).exp()
+    } else {
+        0.0
+    };
+    (1.0 + a * horizon) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// fraction of the sun light going through the atmosphere, for the red,
+/// green and blue wavelengths. Only Rayleigh scattering and the aerosols
+/// are taken into account.
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+    // relative optical mass of the air
+    let m = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608365 * turbidity - 0.04586025;
+    // in micrometers
+    let wavelengths = Color::from([0.680, 0.550, 0.440]);
+    wavelengths.map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * m).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * m).exp();
+        rayleigh * aerosol
+    })
+}
+
+/// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::default();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    let big_y = luminance;
+    Color::from([
+        3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+    ])
+    .map(|c| c.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_sky_is_blue_and_hazy_sky_is_white() {
+        let up = Vec3::from([0, 1, 0]);
+        let clear = Sky::new(40.0, 0.0, 2.0, 1.0).color(&up);
+        assert!(clear.z > clear.x, "clear sky is {clear}");
+        let hazy = Sky::new(40.0, 0.0, 10.0, 1.0).color(&up);
+        let ratio = |c: Color| c.z / c.x;
+        assert!(ratio(hazy) < ratio(clear), "{hazy} is bluer than {clear}");
+    }
+
+    #[test]
+    fn sun_is_where_it_is_sampled() {
+        let sky = Sky::new(25.0, 60.0, 3.0, 1.0);
+        let Some(Light::Sun { direction, .. }) = sky.light() else {
+            panic!("the sun is above the horizon");
+        };
+        assert!(sky.color(&direction).length() > 100.0 * sky.color(&-direction).length());
+        // red goes through the atmosphere more easily
+        let sun = sky.sun_radiance;
+        assert!(sun.x > sun.y && sun.y > sun.z, "the sun is {sun}");
+
+        assert!(Sky::new(-5.0, 60.0, 3.0, 1.0).light().is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use std::{path::Path, sync::Arc};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    tonemap::srgb_eotf,
+    vec3::{Color, Point3, Vec3},
+};
+
+/// what gives its color to a material, depending on where it is hit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Texture {
+    Solid(Color),
+    /// 3D checker pattern, made of cubes of size `scale`
+    Checker {
+        scale: f64,
+        even: Box<Texture>,
+        odd: Box<Texture>,
+    },
+    /// mapped using the (u, v) coordinates of the hit point
+    Image(Arc<ImageTexture>),
+    /// marble-like pattern based on Perlin noise
+    Noise {
+        perlin: Arc<Perlin>,
+        scale: f64,
+    },
+}
+
+impl Texture {
+    pub fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { scale, even, odd } => {
+                let inv = 1.0 / scale;
+                let sum = (inv * p.x).floor() as i64
+                    + (inv * p.y).floor() as i64
+                    + (inv * p.z).floor() as i64;
+                if sum % 2 == 0 {
+                    even.value(u, v, p)
+                } else {
+                    odd.value(u, v, p)
+                }
+            }
+            Texture::Image(img) => img.value(u, v),
+            Texture::Noise { perlin, scale } => {
+                let s = *scale * p;
+                Color::from([1, 1, 1]) * 0.5 * (1.0 + (s.z + 10.0 * perlin.turbulence(&s, 7)).sin())
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// linear colors, row by row starting from the top of the image
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn load(path: &Path) -> image::ImageResult<Self> {
+        let img = image::open(path)?.into_rgb8();
+        let pixels = img
+            .pixels()
+            .map(|px| {
+                let c = Color::from([px[0] as f64, px[1] as f64, px[2] as f64]) / 255.0;
+                // images are sRGB encoded, but we work with linear colors
+                c.map(srgb_eotf)
+            })
+            .collect();
+        Ok(Self {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            pixels,
+        })
+    }
+
+    fn value(&self, u: f64, v: f64) -> Color {
+        if self.pixels.is_empty() {
+            // cyan to make it obvious something is wrong
+            return Color::from([0, 1, 1]);
+        }
+        // v is pointing up whereas the image rows start from the top
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
+
+const PERLIN_POINT_COUNT: usize = 256;
+
+/// Perlin noise, using random unit vectors on the lattice points
+#[derive(Debug, PartialEq)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// the noise is fully determined by the seed
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ranvec = (0..PERLIN_POINT_COUNT)
+            .map(|_| Vec3::random_range(&mut rng, -1.0, 1.0).unit())
+            .collect();
+        let mut perm = || {
+            let mut p = (0..PERLIN_POINT_COUNT).collect::<Vec<_>>();
+            p.shuffle(&mut rng);
+            p
+        };
+        Self {
+            ranvec,
+            perm_x: perm(),
+            perm_y: perm(),
+            perm_z: perm(),
+        }
+    }
+
+    /// smooth noise in [-1;1]
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mask = PERLIN_POINT_COUNT as i64 - 1;
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, cell) in row.iter_mut().enumerate() {
+                    let idx = self.perm_x[((i + di as i64) & mask) as usize]
+                        ^ self.perm_y[((j + dj as i64) & mask) as usize]
+                        ^ self.perm_z[((k + dk as i64) & mask) as usize];
+                    *cell = self.ranvec[idx];
+                }
+            }
+        }
+
+        // hermite smoothing to avoid grid artifacts
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+        let mut accum = 0.0;
+        for (di, plane) in c.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, cell) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::from([u - fi, v - fj, w - fk]);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * cell.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// sum of several octaves of noise
+    pub fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_parity() {
+        let black = Color::from([0, 0, 0]);
+        let white = Color::from([1, 1, 1]);
+        let checker = Texture::Checker {
+            scale: 0.5,
+            even: Box::new(Texture::Solid(black)),
+            odd: Box::new(Texture::Solid(white)),
+        };
+        for (p, expected) in [
+            ([0.25, 0.25, 0.25], black),
+            ([0.75, 0.25, 0.25], white),
+            ([0.75, 0.75, 0.25], black),
+            ([0.75, 0.75, 0.75], white),
+            // the pattern keeps alternating across 0
+            ([-0.25, 0.25, 0.25], white),
+            ([-0.25, -0.25, 0.25], black),
+            ([-0.25, -0.25, -0.25], white),
+        ] {
+            assert_eq!(checker.value(0.0, 0.0, &Vec3::from(p)), expected, "{p:?}");
+        }
+    }
+
+    #[test]
+    fn image_uv() {
+        let [top_left, top_right, bottom_left, bottom_right] =
+            [[1, 0, 0], [0, 1, 0], [0, 0, 1], [1, 1, 1]].map(Color::from);
+        let img = ImageTexture {
+            width: 2,
+            height: 2,
+            pixels: vec![top_left, top_right, bottom_left, bottom_right],
+        };
+        // v goes up, the rows go down
+        assert_eq!(img.value(0.25, 0.25), bottom_left);
+        assert_eq!(img.value(0.75, 0.25), bottom_right);
+        assert_eq!(img.value(0.25, 0.75), top_left);
+        assert_eq!(img.value(0.75, 0.75), top_right);
+        // the edges are included, and the coordinates outside are clamped
+        assert_eq!(img.value(0.0, 0.0), bottom_left);
+        assert_eq!(img.value(1.0, 1.0), top_right);
+        assert_eq!(img.value(-1.0, 2.0), top_left);
+        assert_eq!(img.value(5.0, -3.0), bottom_right);
+    }
+
+    #[test]
+    fn perlin_range() {
+        let perlin = Perlin::new(3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let noise = Texture::Noise {
+            perlin: Arc::new(Perlin::new(3)),
+            scale: 4.0,
+        };
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for _ in 0..10_000 {
+            let p = Vec3::random_range(&mut rng, -50.0, 50.0);
+            let n = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&n), "noise({p:?}) = {n}");
+            (min, max) = (min.min(n), max.max(n));
+            let t = perlin.turbulence(&p, 7);
+            assert!((0.0..=2.0).contains(&t), "turbulence({p:?}) = {t}");
+            let c = noise.value(0.0, 0.0, &p);
+            assert!((0.0..=1.0).contains(&c.x) && c.x == c.y && c.y == c.z);
+        }
+        // and isn't flat either
+        assert!(min < -0.3 && max > 0.3, "noise in [{min}, {max}]");
+        // on the lattice points, the noise is 0
+        assert_eq!(perlin.noise(&Vec3::from([3, -7, 12])), 0.0);
+    }
+
+    #[test]
+    fn perlin_seed() {
+        let (a, b, other) = (Perlin::new(5), Perlin::new(5), Perlin::new(6));
+        assert_eq!(a, b);
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = (0..100)
+            .map(|_| Vec3::random_range(&mut rng, -10.0, 10.0))
+            .collect::<Vec<_>>();
+        assert!(points.iter().all(|p| a.noise(p) == b.noise(p)));
+        assert!(points.iter().any(|p| a.noise(p) != other.noise(p)));
+    }
+}
@@ -0,0 +1,214 @@
+//! Turning the linear radiance computed by the renderer into displayable
+//! colors: exposure, then a tone mapping operator compressing the highlights
+//! into [0;1], then the sRGB transfer function.
+
+use std::fmt::Display;
+
+#[cfg(feature = "viewer")]
+use eframe::egui::Color32;
+
+use crate::vec3::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMap {
+    /// no compression, everything above 1 is clipped
+    Clamp,
+    /// c / (1 + c), never reaches white
+    Reinhard,
+    /// Reinhard, scaled so that `white_point` maps to white
+    ExtendedReinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// John Hable's filmic curve, from Uncharted 2
+    Uncharted2,
+}
+
+impl ToneMap {
+    pub const ALL: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ExtendedReinhard,
+        ToneMap::Aces,
+        ToneMap::Uncharted2,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "none",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::ExtendedReinhard => "extended-reinhard",
+            ToneMap::Aces => "aces",
+            ToneMap::Uncharted2 => "uncharted2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+}
+
+impl Display for ToneMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// how to display an image, this doesn't change the rendering itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMap,
+    /// in stops, each one doubles the brightness
+    pub exposure: f64,
+    /// smallest radiance mapped to white by the extended Reinhard operator
+    pub white_point: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMap::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// linear radiance to a displayable color, in [0;1] before the sRGB
+    /// transfer function
+    pub fn tone_map(&self, color: Color) -> Color {
+        let color = color * self.exposure.exp2();
+        let mapped = match self.operator {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => color.map(|c| c / (1.0 + c)),
+            ToneMap::ExtendedReinhard => {
+                let white2 = self.white_point * self.white_point;
+                color.map(|c| c * (1.0 + c / white2) / (1.0 + c))
+            }
+            ToneMap::Aces => {
+                color.map(|c| (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14))
+            }
+            ToneMap::Uncharted2 => {
+                // the curve is designed for an exposure bias of 2, and
+                // normalized so that a radiance of 11.2 becomes white
+                let white_scale = 1.0 / hable(11.2);
+                color.map(|c| hable(2.0 * c) * white_scale)
+            }
+        };
+        mapped.map(|c| c.clamp(0.0, 1.0))
+    }
+
+    /// linear radiance to an 8 bits sRGB color
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3] {
+        let c = self.tone_map(color).map(srgb_oetf);
+        [c.x, c.y, c.z].map(|c| (c * 255.999) as u8)
+    }
+
+    #[cfg(feature = "viewer")]
+    pub fn to_color32(&self, color: Color) -> Color32 {
+        let [r, g, b] = self.to_rgb8(color);
+        Color32::from_rgb(r, g, b)
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15; // shoulder strength
+    const B: f64 = 0.50; // linear strength
+    const C: f64 = 0.10; // linear angle
+    const D: f64 = 0.20; // toe strength
+    const E: f64 = 0.02; // toe numerator
+    const F: f64 = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// linear to sRGB encoded, for a component in [0;1]
+pub fn srgb_oetf(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// sRGB encoded to linear, the inverse of `srgb_oetf`
+pub fn srgb_eotf(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(tone_mapping: &ToneMapping, c: f64) -> f64 {
+        tone_mapping.tone_map(Color::from([c, c, c])).x
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        // both sides of the linear segment, which ends at 0.0031308
+        let around_the_breakpoint = [0.003, 0.0031308, 0.0032];
+        let uniform = (0..=100).map(|i| i as f64 / 100.0);
+        for x in uniform.chain(around_the_breakpoint) {
+            let y = srgb_eotf(srgb_oetf(x));
+            assert!((x - y).abs() < 1e-9, "{x} became {y}");
+        }
+    }
+
+    #[test]
+    fn operators_are_monotonic() {
+        for operator in ToneMap::ALL {
+            let tone_mapping = ToneMapping {
+                operator,
+                ..Default::default()
+            };
+            assert!(map(&tone_mapping, 0.0).abs() < 1e-12, "{operator}");
+            let mut prev = 0.0;
+            for i in 0..=2000 {
+                let y = map(&tone_mapping, i as f64 * 0.05);
+                assert!((0.0..=1.0).contains(&y), "{operator}: {y}");
+                assert!(y >= prev, "{operator} decreases at {}", i as f64 * 0.05);
+                prev = y;
+            }
+        }
+    }
+
+    #[test]
+    fn white_points() {
+        let extended = ToneMapping {
+            operator: ToneMap::ExtendedReinhard,
+            white_point: 3.0,
+            ..Default::default()
+        };
+        assert!((map(&extended, 3.0) - 1.0).abs() < 1e-12);
+        assert!(map(&extended, 2.9) < 1.0);
+
+        let uncharted = ToneMapping {
+            operator: ToneMap::Uncharted2,
+            ..Default::default()
+        };
+        assert!((map(&uncharted, 11.2 / 2.0) - 1.0).abs() < 1e-12);
+        assert!(map(&uncharted, 5.0) < 1.0);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(map(&tone_mapping, 0.2), 0.4);
+        assert_eq!(map(&tone_mapping, 0.0), 0.0);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for op in ToneMap::ALL {
+            assert_eq!(ToneMap::from_name(op.name()), Some(op));
+        }
+        assert_eq!(ToneMap::from_name("filmic"), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! Affine transforms, to place, rotate and scale objects. Wrapping an
+//! `Arc` to some geometry in `Transformed` instances it without copying it.
+
+use std::f64::consts::PI;
+
+use auto_ops::*;
+
+use crate::{
+    aabb::Aabb,
+    light::Light,
+    ray::{HitRecord, Hittable, Ray},
+    vec3::{Point3, Vec3},
+};
+
+/// 4x4 matrix, row major
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Mat4([[f64; 4]; 4]);
+
+impl Mat4 {
+    fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4(m)
+    }
+
+    fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = self.0[j][i];
+            }
+        }
+        Mat4(m)
+    }
+
+    fn apply(&self, v: &Vec3, w: f64) -> Vec3 {
+        let m = &self.0;
+        Vec3::from([
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z + m[0][3] * w,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z + m[1][3] * w,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z + m[2][3] * w,
+        ])
+    }
+}
+
+impl_op_ex!(*|a: &Mat4, b: &Mat4| -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..4).map(|k| a.0[i][k] * b.0[k][j]).sum();
+        }
+    }
+    Mat4(m)
+});
+
+/// an affine transform, along with its inverse so that it never has to be
+/// computed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+}
+
+impl Transform {
+    pub fn translation(offset: &Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        let mut inverse = Mat4::identity();
+        for axis in 0..3 {
+            matrix.0[axis][3] = offset[axis];
+            inverse.0[axis][3] = -offset[axis];
+        }
+        Self { matrix, inverse }
+    }
+
+    /// scale by a (possibly different) factor along each axis, which must
+    /// not be 0
+    pub fn scaling(factors: &Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        let mut inverse = Mat4::identity();
+        for axis in 0..3 {
+            matrix.0[axis][axis] = factors[axis];
+            inverse.0[axis][axis] = 1.0 / factors[axis];
+        }
+        Self { matrix, inverse }
+    }
+
+    /// rotation around `axis` going through the origin, the angle is in
+    /// degrees, counter clockwise when `axis` points towards the viewer
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = (degrees * PI / 180.0).sin_cos();
+        let t = 1.0 - cos;
+        let mut matrix = Mat4::identity();
+        matrix.0[0][0] = t * a.x * a.x + cos;
+        matrix.0[0][1] = t * a.x * a.y - sin * a.z;
+        matrix.0[0][2] = t * a.x * a.z + sin * a.y;
+        matrix.0[1][0] = t * a.x * a.y + sin * a.z;
+        matrix.0[1][1] = t * a.y * a.y + cos;
+        matrix.0[1][2] = t * a.y * a.z - sin * a.x;
+        matrix.0[2][0] = t * a.x * a.z - sin * a.y;
+        matrix.0[2][1] = t * a.y * a.z + sin * a.x;
+        matrix.0[2][2] = t * a.z * a.z + cos;
+        // rotations are orthogonal
+        Self {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    /// apply `self`, then `next`
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    #[cfg(test)]
+    fn inverse(&self) -> Self {
+        Self {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        self.matrix.apply(p, 1.0)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.apply(v, 0.0)
+    }
+
+    /// normals are transformed by the inverse transpose, to stay
+    /// perpendicular to the transformed surface. The result isn't normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.inverse.0;
+        Vec3::from([
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        ])
+    }
+
+    /// the factor by which all the lengths are scaled, if the transform
+    /// preserves angles (only rotations, translations and uniform scaling)
+    pub fn uniform_scale(&self) -> Option<f64> {
+        let columns = [0, 1, 2].map(|axis| {
+            let mut unit = [0.0; 3];
+            unit[axis] = 1.0;
+            self.vector(&Vec3::from(unit))
+        });
+        let scale = columns[0].length();
+        let tolerance = 1e-9 * scale;
+        let same_length = columns
+            .iter()
+            .all(|c| (c.length() - scale).abs() <= tolerance);
+        let orthogonal =
+            (0..3).all(|i| columns[i].dot(&columns[(i + 1) % 3]).abs() <= tolerance * scale);
+        (same_length && orthogonal).then_some(scale)
+    }
+
+    /// box surrounding the transformed box
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        if bbox.min.x > bbox.max.x || bbox.min.y > bbox.max.y || bbox.min.z > bbox.max.z {
+            return *bbox;
+        }
+        (0..8).fold(Aabb::empty(), |acc, corner| {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    bbox.min[axis]
+                } else {
+                    bbox.max[axis]
+                }
+            };
+            let p = self.point(&Vec3::from([pick(0), pick(1), pick(2)]));
+            acc.surrounding(&Aabb::new(p, p))
+        })
+    }
+}
+
+/// an object moved by a transform. The object itself stays in its own
+/// space, only the rays going through it are transformed.
+#[derive(Debug)]
+pub struct Transformed<T> {
+    object: T,
+    transform: Transform,
+    bbox: Aabb,
+}
+
+impl<T: Hittable> Transformed<T> {
+    pub fn new(object: T, transform: Transform) -> Self {
+        let bbox = transform.bounding_box(&object.bounding_box());
+        Self {
+            object,
+            transform,
+            bbox,
+        }
+    }
+}
+
+impl<T: Hittable> Hittable for Transformed<T> {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<HitRecord<'_>> {
+        // the direction isn't normalized, so that t is the same in both spaces
+        let local_ray = Ray {
+            orig: self.transform.inverse.apply(&ray.orig, 1.0),
+            dir: self.transform.inverse.apply(&ray.dir, 0.0),
+            time: ray.time,
+        };
+        let hit = self.object.hit(&local_ray, tmin, tmax)?;
+        // the normal already faces the ray, and still does once transformed
+        Some(HitRecord {
+            p: self.transform.point(&hit.p),
+            normal: self.transform.normal(&hit.normal).unit(),
+            ..hit
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.object
+            .lights()
+            .iter()
+            .filter_map(|light| light.transformed(&self.transform))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Material, Sphere},
+        texture::Texture,
+    };
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{a} != {b}");
+    }
+
+    fn transform() -> Transform {
+        Transform::scaling(&Vec3::from([2.0, 0.5, 3.0]))
+            .then(&Transform::rotation(&Vec3::from([1, 2, 3]), 37.0))
+            .then(&Transform::translation(&Vec3::from([4, -5, 6])))
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let t = transform();
+        let p = Vec3::from([0.3, -7.0, 2.5]);
+        assert_near(&t.inverse().point(&t.point(&p)), &p);
+        assert_near(&t.point(&t.inverse().point(&p)), &p);
+        assert_near(&t.inverse().vector(&t.vector(&p)), &p);
+        assert_eq!(t.uniform_scale(), None);
+
+        let rigid = Transform::rotation(&Vec3::from([0, 1, 0]), 30.0)
+            .then(&Transform::scaling(&Vec3::from([2, 2, 2])));
+        let scale = rigid.uniform_scale().unwrap();
+        assert!((scale - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moved_sphere_matches_sphere() {
+        let material = Material::Lambertian {
+            albedo: Texture::Solid(Vec3::from([0.5, 0.5, 0.5])),
+        };
+        let sphere = |center: Vec3, radius: f64| Sphere {
+            center,
+            radius,
+            material: material.clone(),
+        };
+        let t = Transform::rotation(&Vec3::from([1, 1, 0]), 60.0)
+            .then(&Transform::scaling(&Vec3::from([2, 2, 2])))
+            .then(&Transform::translation(&Vec3::from([1, 2, 3])));
+        let moved = Transformed::new(sphere(Vec3::from([1, 0, 0]), 0.5), t.clone());
+        let expected = sphere(t.point(&Vec3::from([1, 0, 0])), 1.0);
+
+        let ray = Ray {
+            orig: Vec3::from([-10, 0, 0]),
+            dir: expected.center - Vec3::from([-10.0, 0.3, 0.0]),
+            time: 0.0,
+        };
+        let hit = moved.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        let expected_hit = expected.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - expected_hit.t).abs() < 1e-9);
+        assert_near(&hit.p, &expected_hit.p);
+        assert_near(&hit.normal, &expected_hit.normal);
+        assert_eq!(hit.face, expected_hit.face);
+
+        let bbox = moved.bounding_box();
+        let expected_bbox = expected.bounding_box();
+        for axis in 0..3 {
+            assert!(bbox.min[axis] <= expected_bbox.min[axis] + 1e-9);
+            assert!(bbox.max[axis] >= expected_bbox.max[axis] - 1e-9);
+        }
+    }
+}
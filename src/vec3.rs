@@ -1,27 +1,26 @@
-use crate::egui::Color32;
-use std::fmt::Display;
+use std::{fmt::Display, ops::Index};
 
 use auto_ops::*;
-use rand::{distributions::Uniform, random, thread_rng, Rng};
+use rand::{distributions::Uniform, Rng};
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
-pub(crate) struct Vec3 {
-    pub(crate) x: f64,
-    pub(crate) y: f64,
-    pub(crate) z: f64,
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
 }
 
-// pub(crate) const ZERO: Vec3 = Vec3 {
+// pub const ZERO: Vec3 = Vec3 {
 //     x: 0.0,
 //     y: 0.0,
 //     z: 0.0,
 // };
 
 /// 3D point
-pub(crate) type Point3 = Vec3;
+pub type Point3 = Vec3;
 
 /// RGB color
-pub(crate) type Color = Vec3;
+pub type Color = Vec3;
 
 impl From<[f64; 3]> for Vec3 {
     fn from(x: [f64; 3]) -> Self {
@@ -43,16 +42,6 @@ impl From<[isize; 3]> for Vec3 {
     }
 }
 
-impl From<Vec3> for Color32 {
-    fn from(c: Vec3) -> Self {
-        Color32::from_rgb(
-            (c.x * 255.999) as _,
-            (c.y * 255.999) as _,
-            (c.z * 255.999) as _,
-        )
-    }
-}
-
 impl Display for Vec3 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({},{},{})", self.x, self.y, self.z)
@@ -60,20 +49,20 @@ impl Display for Vec3 {
 }
 
 impl Vec3 {
-    pub(crate) fn length(&self) -> f64 {
+    pub fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
 
-    pub(crate) fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    pub(crate) fn dot(&self, v: &Vec3) -> f64 {
+    pub fn dot(&self, v: &Vec3) -> f64 {
         let u = self;
         u.x * v.x + u.y * v.y + u.z * v.z
     }
 
-    pub(crate) fn cross(&self, v: &Vec3) -> Vec3 {
+    pub fn cross(&self, v: &Vec3) -> Vec3 {
         let u = self;
         Vec3 {
             x: u.y * v.z - u.z * v.y,
@@ -82,28 +71,29 @@ impl Vec3 {
         }
     }
 
-    pub(crate) fn unit(&self) -> Self {
+    pub fn unit(&self) -> Self {
         self / self.length()
     }
 
-    pub(crate) fn sqrt(&self) -> Self {
+    /// apply `f` to each component
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
         Self {
-            x: self.x.sqrt(),
-            y: self.y.sqrt(),
-            z: self.z.sqrt(),
+            x: f(self.x),
+            y: f(self.y),
+            z: f(self.z),
         }
     }
 
-    pub(crate) fn random() -> Self {
+    /// random vector with all components in [0;1)
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Vec3 {
-            x: random(),
-            y: random(),
-            z: random(),
+            x: rng.gen(),
+            y: rng.gen(),
+            z: rng.gen(),
         }
     }
 
-    pub(crate) fn random_range(min: f64, max: f64) -> Self {
-        let mut rng = thread_rng();
+    pub fn random_range<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
         let d = Uniform::new(min, max);
         Vec3 {
             x: rng.sample(d),
@@ -112,46 +102,40 @@ impl Vec3 {
         }
     }
 
-    pub(crate) fn random_in_unit_sphere() -> Self {
-        loop {
-            let p = Self::random_range(-1.0, 1.0);
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+    pub fn is_near_zero(&self) -> bool {
+        let s = 1e-8;
+        (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
     }
 
-    pub(crate) fn random_in_unit_disk() -> Self {
-        loop {
-            let mut p = Self::random_range(-1.0, 1.0);
-            p.z = 0.0;
-            if p.length_squared() < 1.0 {
-                return p;
-            }
+    /// component-wise minimum
+    pub fn min(&self, v: &Vec3) -> Self {
+        Vec3 {
+            x: self.x.min(v.x),
+            y: self.y.min(v.y),
+            z: self.z.min(v.z),
         }
     }
 
-    /// Lambertian diffusion
-    #[allow(dead_code)]
-    pub(crate) fn random_unit_vector() -> Self {
-        Self::random_in_unit_sphere().unit()
-    }
-
-    /// uniform scatter direction away from the hit point, widely used
-    /// before the adoption of Lambertian diffusion.
-    #[allow(dead_code)]
-    pub(crate) fn random_in_hemisphere(normal: &Vec3) -> Self {
-        let in_unit_sphere = Self::random_in_unit_sphere();
-        if in_unit_sphere.dot(normal) > 0.0 {
-            in_unit_sphere
-        } else {
-            -in_unit_sphere
+    /// component-wise maximum
+    pub fn max(&self, v: &Vec3) -> Self {
+        Vec3 {
+            x: self.x.max(v.x),
+            y: self.y.max(v.y),
+            z: self.z.max(v.z),
         }
     }
+}
 
-    pub(crate) fn is_near_zero(&self) -> bool {
-        let s = 1e-8;
-        (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("invalid axis {axis} for Vec3"),
+        }
     }
 }
 
@@ -0,0 +1,200 @@
+//! Regression tests rendering a few scenes with a fixed seed, and comparing
+//! them with the reference images in `tests/references`. Renders are
+//! reproducible, so the images only differ from the references because of
+//! a change in the renderer, or floating point differences between
+//! platforms (which the tolerances are there for).
+//!
+//! When the images are expected to change, run the tests with
+//! `UPDATE_REFERENCES=1` to write new references, and look at them before
+//! committing them. On failure, the image and its difference with the
+//! reference are written to `target/reference-diffs`.
+
+use std::path::{Path, PathBuf};
+
+use raytracing::{
+    load_scene, render, Color, ImageBuffer, RenderSettings, SamplerKind, ToneMapping, MAX_DEPTH,
+};
+
+const SEED: u64 = 1;
+const SAMPLES_PER_PIXEL: usize = 16;
+
+/// root mean square error on the displayed colors, in [0;1]
+const MAX_RMSE: f64 = 0.02;
+
+/// structural similarity of the displayed luminance, 1 for identical images
+const MIN_SSIM: f64 = 0.95;
+
+/// a scene rendered at a small size, to keep the tests fast
+struct Case {
+    name: &'static str,
+    scene: &'static str,
+    width: usize,
+    height: usize,
+}
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn render_case(case: &Case) -> ImageBuffer {
+    let mut scene = load_scene(case.scene, SEED).expect("cannot load the scene");
+    scene.image_width = Some(case.width);
+    scene.aspect_ratio = Some(case.width as f64 / case.height as f64);
+    let settings = RenderSettings {
+        seed: SEED,
+        samples_per_pixel: SAMPLES_PER_PIXEL,
+        max_depth: MAX_DEPTH,
+        sampler: SamplerKind::Sobol,
+        camera: scene.camera.clone(),
+    };
+    render(&scene, &settings)
+}
+
+/// the colors as they are displayed, in [0;1]
+fn display(pixels: impl Iterator<Item = Color>) -> Vec<Color> {
+    let tone_mapping = ToneMapping::default();
+    pixels
+        .map(|c| {
+            let [r, g, b] = tone_mapping.to_rgb8(c);
+            Color::from([r as f64, g as f64, b as f64]) / 255.0
+        })
+        .collect()
+}
+
+fn load_reference(path: &Path) -> (usize, usize, Vec<Color>) {
+    let img = image::open(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()))
+        .into_rgb32f();
+    let pixels = img
+        .pixels()
+        .map(|px| Color::from([px.0[0] as f64, px.0[1] as f64, px.0[2] as f64]));
+    (img.width() as usize, img.height() as usize, display(pixels))
+}
+
+fn rmse(a: &[Color], b: &[Color]) -> f64 {
+    let sum = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).length_squared())
+        .sum::<f64>();
+    (sum / (3 * a.len()) as f64).sqrt()
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// mean structural similarity, over 8x8 windows going 4 pixels at a time
+fn ssim(a: &[Color], b: &[Color], width: usize, height: usize) -> f64 {
+    const WINDOW: usize = 8;
+    const STEP: usize = 4;
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let mut total = 0.0;
+    let mut count = 0;
+    for y in (0..=height.saturating_sub(WINDOW)).step_by(STEP) {
+        for x in (0..=width.saturating_sub(WINDOW)).step_by(STEP) {
+            let window = |img: &[Color]| {
+                (y..(y + WINDOW).min(height))
+                    .flat_map(|y| (x..(x + WINDOW).min(width)).map(move |x| y * width + x))
+                    .map(|i| luminance(&img[i]))
+                    .collect::<Vec<_>>()
+            };
+            let (wa, wb) = (window(a), window(b));
+            let n = wa.len() as f64;
+            let (mean_a, mean_b) = (wa.iter().sum::<f64>() / n, wb.iter().sum::<f64>() / n);
+            let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+            for (va, vb) in wa.iter().zip(&wb) {
+                var_a += (va - mean_a) * (va - mean_a) / n;
+                var_b += (vb - mean_b) * (vb - mean_b) / n;
+                covar += (va - mean_a) * (vb - mean_b) / n;
+            }
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            count += 1;
+        }
+    }
+    total / count as f64
+}
+
+/// the differences, amplified to be visible
+fn save_diff(path: &Path, a: &[Color], b: &[Color], width: usize, height: usize) {
+    let raw = a
+        .iter()
+        .zip(b)
+        .flat_map(|(a, b)| {
+            let diff = (a - b).map(|c| (4.0 * c.abs()).min(1.0));
+            [diff.x, diff.y, diff.z].map(|c| (c * 255.0).round() as u8)
+        })
+        .collect();
+    image::RgbImage::from_raw(width as u32, height as u32, raw)
+        .expect("buffer size doesn't match image dimensions")
+        .save(path)
+        .expect("cannot write the diff image");
+}
+
+fn check(case: Case) {
+    let img = render_case(&case);
+    let reference = manifest_path(&format!("tests/references/{}.exr", case.name));
+    if std::env::var_os("UPDATE_REFERENCES").is_some() {
+        img.save(&reference, &ToneMapping::default())
+            .expect("cannot write the reference");
+        return;
+    }
+
+    let (width, height, expected) = load_reference(&reference);
+    assert_eq!(
+        (width, height),
+        (case.width, case.height),
+        "the reference has another size"
+    );
+    let actual = display(img.pixels().iter().map(|(sum, n)| sum / (*n).max(1) as f64));
+    let rmse = rmse(&actual, &expected);
+    let ssim = ssim(&actual, &expected, width, height);
+    if rmse > MAX_RMSE || ssim < MIN_SSIM {
+        let dir = manifest_path("target/reference-diffs");
+        std::fs::create_dir_all(&dir).expect("cannot create the diff directory");
+        let image = dir.join(format!("{}.exr", case.name));
+        let diff = dir.join(format!("{}-diff.png", case.name));
+        img.save(&image, &ToneMapping::default())
+            .expect("cannot write the image");
+        save_diff(&diff, &actual, &expected, width, height);
+        panic!(
+            "{} doesn't match its reference: RMSE {rmse:.4} (max {MAX_RMSE}), \
+             SSIM {ssim:.4} (min {MIN_SSIM}). See {} and {}",
+            case.name,
+            image.display(),
+            diff.display()
+        );
+    }
+}
+
+#[test]
+fn three_spheres() {
+    check(Case {
+        name: "three_spheres",
+        scene: concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/three_spheres.toml"),
+        width: 64,
+        height: 36,
+    });
+}
+
+#[test]
+fn cornell_box() {
+    check(Case {
+        name: "cornell_box",
+        scene: "cornell",
+        width: 48,
+        height: 48,
+    });
+}
+
+#[test]
+fn glass() {
+    check(Case {
+        name: "glass",
+        scene: concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/glass.toml"),
+        width: 64,
+        height: 48,
+    });
+}